@@ -27,21 +27,23 @@ fn wrap(m: u32, n: u32) -> u32 {
     return n % m;
 }
 
+/// Dithers one source pixel into a `scale`x`scale` block of output pixels.
 #[inline]
-fn calc_dithered_pixels_4x4(old_pixel: &Luma<u8>, x: u32, y: u32) -> u16 {
+fn calc_dithered_pixels(old_pixel: &Luma<u8>, x: u32, y: u32, scale: u32) -> u16 {
     // Increase brightness 1.5x
     let ref old_pixel = Luma([(((old_pixel[0] as f32 / 255.0) * 1.5) * 255.0) as u8]);
 
     let mut res = 0u16;
     let mut i = 0;
-    for y_offset in 0..4 {
-        for x_offset in 0..4 {
-            let wrap_x = wrap(*NOISE_WIDTH, x * 4 + x_offset);
-            let wrap_y = wrap(*NOISE_HEIGHT, y * 4 + y_offset);
+    let first_bit = 1u16 << (scale * scale - 1);
+    for y_offset in 0..scale {
+        for x_offset in 0..scale {
+            let wrap_x = wrap(*NOISE_WIDTH, x * scale + x_offset);
+            let wrap_y = wrap(*NOISE_HEIGHT, y * scale + y_offset);
 
             let noise_pixel = NOISE_IMG.get_pixel(wrap_x, wrap_y);
             if is_bright(noise_pixel, old_pixel) {
-                res |= 0x8000 >> i;
+                res |= first_bit >> i;
             } // else default value (0 bit)
             i += 1;
         }
@@ -66,16 +68,17 @@ fn calc_dither_cache_index(old_pixel: &Luma<u8>, x: u32, y: u32, width: u32) ->
     (y * line_width) + (x * PIX_WIDTH) + pix_luma_val
 }
 
-/// The u16 contains a 4x4 array of pixel bits (1 = black, 0 = white)
-pub fn calc_full_cache(width: u32, height: u32) -> Vec<u16> {
+/// The u16 contains a `scale`x`scale` array of pixel bits (1 = black, 0 = white).
+/// Scales of up to 4 fit.
+pub fn calc_full_cache(width: u32, height: u32, scale: u32) -> Vec<u16> {
+    assert!((1..=4).contains(&scale));
     let mut dither_cache = vec![0u16; calc_dither_cache_len(width, height)];
 
     // Pre calculate
     for y in 0..height {
         for x in 0..width {
             for luma in 0..=255 {
-                //let res = instance.calc_dithered_pixels_4x4(&Luma([luma]), x, y);
-                let res = calc_dithered_pixels_4x4(&Luma([luma]), x, y);
+                let res = calc_dithered_pixels(&Luma([luma]), x, y, scale);
                 dither_cache[calc_dither_cache_index(&Luma([luma]), x, y, width)] = res;
             }
         }
@@ -0,0 +1,75 @@
+//! Saving into a reserved slot before exiting and on low battery, so progress
+//! doesn't get lost. The slot can be loaded with "Continue" on the next launch.
+
+use crate::macros::{self, Macro};
+use crate::saves;
+use doomgeneric::input::KeyData;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+/// Index of the savegame file (`doomsav5.dsg`). It's the last slot in the game's menus.
+pub const SLOT: usize = saves::SLOTS - 1;
+/// Max time for the game to write the savegame after the keys were typed
+const SAVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prevent typing two save sequences at once (e.g. low battery while exiting)
+static SAVING: AtomicBool = AtomicBool::new(false);
+
+fn save_path() -> PathBuf {
+    saves::slot_path(SLOT)
+}
+
+fn backup_path() -> PathBuf {
+    save_path().with_extension("dsg.bak")
+}
+
+pub fn exists() -> bool {
+    save_path().exists()
+}
+
+/// Menu items of the save and load menus can be selected by their number
+fn save_macro() -> Macro {
+    Macro::parse(&format!(
+        "Autosave: <f2> <wait:300> {} <enter> <wait:100> autosave <enter>",
+        SLOT + 1
+    ))
+    .unwrap()
+}
+
+/// Type the save sequence and wait until the game wrote the savegame.
+/// Returns whether it got saved.
+pub fn save(keydata_tx: &Sender<KeyData>) -> bool {
+    if SAVING.swap(true, Ordering::Relaxed) {
+        return false;
+    }
+
+    // The game keeps the name of a used slot for editing. An empty slot starts with no
+    // name, so the old save is moved away and only restored if saving fails.
+    let had_backup = std::fs::rename(save_path(), backup_path()).is_ok();
+    macros::play_blocking(save_macro(), keydata_tx);
+
+    let start = Instant::now();
+    while !exists() && start.elapsed() < SAVE_TIMEOUT {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let saved = exists();
+    if saved {
+        info!("Autosaved in {:?}", start.elapsed());
+        if had_backup {
+            std::fs::remove_file(backup_path()).ok();
+        }
+    } else {
+        // Most likely not in a level
+        warn!("Autosave failed. The game didn't write {:?}.", save_path());
+        if had_backup {
+            if let Err(err) = std::fs::rename(backup_path(), save_path()) {
+                error!("Failed to restore the previous autosave: {err}");
+            }
+        }
+    }
+
+    SAVING.store(false, Ordering::Relaxed);
+    saved
+}
@@ -0,0 +1,161 @@
+//! Battery state from the power_supply class in sysfs
+
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_POWER_SUPPLY_ROOT: &str = "/sys/class/power_supply";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeState {
+    Charging,
+    Discharging,
+    Full,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryStatus {
+    pub percentage: u8,
+    pub state: ChargeState,
+}
+
+/// Where the battery state comes from. Can be replaced to simulate a discharging battery.
+pub trait BatterySource {
+    fn read(&self) -> Option<BatteryStatus>;
+}
+
+/// Reads the first power supply of the type "Battery" below the root, since the name
+/// differs between devices (bq27441-0 on the rM 1, max77818_battery on the rM 2).
+/// The root can point to fake files for testing.
+pub struct SysfsBattery {
+    root: PathBuf,
+}
+
+impl SysfsBattery {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn battery_dir(&self) -> Option<PathBuf> {
+        let mut dirs: Vec<PathBuf> = std::fs::read_dir(&self.root)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        dirs.sort();
+        dirs.into_iter()
+            .find(|dir| read_trimmed(&dir.join("type")).as_deref() == Some("Battery"))
+    }
+}
+
+impl BatterySource for SysfsBattery {
+    fn read(&self) -> Option<BatteryStatus> {
+        let dir = self.battery_dir()?;
+        let percentage = read_trimmed(&dir.join("capacity"))?.parse::<u8>().ok()?;
+        let state = match read_trimmed(&dir.join("status")).as_deref() {
+            Some("Charging") => ChargeState::Charging,
+            Some("Discharging") | Some("Not charging") => ChargeState::Discharging,
+            Some("Full") => ChargeState::Full,
+            _ => ChargeState::Unknown,
+        };
+        Some(BatteryStatus {
+            percentage: percentage.min(100),
+            state,
+        })
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Power supply tree with a charger and a battery, removed when dropped
+    struct FakeSysfs {
+        root: PathBuf,
+    }
+
+    impl FakeSysfs {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "doomarkable-{}-power_supply-{name}",
+                std::process::id()
+            ));
+            let sysfs = Self { root };
+            sysfs.write("ac", "type", "Mains\n");
+            sysfs.write("ac", "capacity", "100\n");
+            sysfs.write("bq27441-0", "type", "Battery\n");
+            sysfs
+        }
+
+        fn write(&self, supply: &str, file: &str, content: &str) {
+            let dir = self.root.join(supply);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+
+        fn read(&self) -> Option<BatteryStatus> {
+            SysfsBattery::new(&self.root).read()
+        }
+    }
+
+    impl Drop for FakeSysfs {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    #[test]
+    fn picks_the_battery() {
+        let sysfs = FakeSysfs::new("battery");
+        sysfs.write("bq27441-0", "capacity", "42\n");
+        sysfs.write("bq27441-0", "status", "Discharging\n");
+        assert_eq!(
+            sysfs.read(),
+            Some(BatteryStatus {
+                percentage: 42,
+                state: ChargeState::Discharging,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_charge_states() {
+        let sysfs = FakeSysfs::new("states");
+        sysfs.write("bq27441-0", "capacity", "80\n");
+        for (status, state) in [
+            ("Charging", ChargeState::Charging),
+            ("Discharging", ChargeState::Discharging),
+            ("Not charging", ChargeState::Discharging),
+            ("Full", ChargeState::Full),
+            ("Unknown", ChargeState::Unknown),
+            ("", ChargeState::Unknown),
+        ] {
+            sysfs.write("bq27441-0", "status", &format!("{status}\n"));
+            assert_eq!(sysfs.read().map(|status| status.state), Some(state));
+        }
+    }
+
+    #[test]
+    fn needs_a_valid_capacity() {
+        let sysfs = FakeSysfs::new("capacity");
+        sysfs.write("bq27441-0", "status", "Discharging\n");
+        assert_eq!(sysfs.read(), None);
+        sysfs.write("bq27441-0", "capacity", "many\n");
+        assert_eq!(sysfs.read(), None);
+        sysfs.write("bq27441-0", "capacity", "-1\n");
+        assert_eq!(sysfs.read(), None);
+    }
+
+    #[test]
+    fn nothing_without_a_battery() {
+        let sysfs = FakeSysfs::new("none");
+        std::fs::remove_dir_all(sysfs.root.join("bq27441-0")).unwrap();
+        assert_eq!(sysfs.read(), None);
+        assert_eq!(SysfsBattery::new(sysfs.root.join("missing")).read(), None);
+    }
+}
@@ -1,10 +1,34 @@
+use crate::preprocess;
+use crate::regions::Treatment;
+use crate::render_scale::{RenderScale, SOURCE_HEIGHT, SOURCE_WIDTH};
 use libremarkable::image::{DynamicImage, GenericImageView, GrayImage, Luma};
+use std::io::Cursor;
 
-pub struct CachedDither4X {
+/// Averages the channels. No need for srgb correction.
+fn grayscale(input_image: &DynamicImage) -> GrayImage {
+    //let old_img = libremarkable::image::imageops::grayscale(input_image);
+    libremarkable::image::GrayImage::from_fn(input_image.width(), input_image.height(), |x, y| {
+        let pixel = input_image.get_pixel(x, y);
+        /*let r = pixel.data[0] as f32 / 255.0 * 1.5;
+        let g = pixel.data[1] as f32 / 255.0 * 1.5;
+        let b = pixel.data[2] as f32 / 255.0 * 1.5;
+        let gray = (1f32.min((r + g + b) / 3.0) * 255f32) as u8;
+        Luma([gray])*/
+
+        let r = pixel.0[0] as u16;
+        let g = pixel.0[1] as u16;
+        let b = pixel.0[2] as u16;
+        Luma([((r + g + b) / 3) as u8])
+    })
+}
+
+pub struct CachedDither {
+    scale: RenderScale,
     dither_cache: Vec<u16>,
+    preprocessing: Option<preprocess::Settings>,
 }
 
-impl CachedDither4X {
+impl CachedDither {
     fn convert_vec_u8_to_vec_u16(vec: Vec<u8>) -> Vec<u16> {
         assert!(vec.len() % 2 == 0);
         vec.chunks_exact(2)
@@ -12,17 +36,40 @@ impl CachedDither4X {
             .collect()
     }
 
-    pub fn new(raw_dither_cache: Vec<u8>) -> Self {
+    pub fn new(scale: RenderScale, raw_dither_cache: Vec<u8>) -> Self {
         Self {
+            scale,
             dither_cache: Self::convert_vec_u8_to_vec_u16(raw_dither_cache),
+            preprocessing: None,
         }
     }
 
+    /// Decompresses the cache of the given scale
+    pub fn load(scale: RenderScale) -> Self {
+        let mut dither_cache_raw = Cursor::new(Vec::with_capacity(
+            Self::calc_dither_cache_len(SOURCE_WIDTH, SOURCE_HEIGHT) * 2,
+        ));
+        zstd::stream::copy_decode(
+            Cursor::new(scale.compressed_dither_cache()),
+            &mut dither_cache_raw,
+        )
+        .unwrap();
+        Self::new(scale, dither_cache_raw.into_inner())
+    }
+
+    pub fn scale(&self) -> RenderScale {
+        self.scale
+    }
+
+    pub fn set_preprocessing(&mut self, preprocessing: Option<preprocess::Settings>) {
+        self.preprocessing = preprocessing;
+    }
+
     /// TODO: Fix duplication in build/blue_noise_calculator.rs
     #[inline]
     fn calc_dither_cache_index(old_pixel: &Luma<u8>, x: u32, y: u32) -> usize {
         const PIX_WIDTH: usize = 256; // 256 shades of gray (each with its own dithered u16)
-        const LINE_WIDTH: usize = 320 as usize * PIX_WIDTH; // TODO: 320 is still hardcoded!
+        const LINE_WIDTH: usize = SOURCE_WIDTH as usize * PIX_WIDTH;
         let x = x as usize;
         let y = y as usize;
         let pix_luma_val = old_pixel[0] as usize;
@@ -35,45 +82,52 @@ impl CachedDither4X {
         width as usize * height as usize * 256
     }
 
+    /// Bits of a scale x scale block, row by row, starting at the lowest bit
     #[inline]
-    pub fn get_dithered_pixels_4x4(&self, old_pixel: &Luma<u8>, x: u32, y: u32) -> u16 {
+    pub fn get_dithered_pixels(&self, old_pixel: &Luma<u8>, x: u32, y: u32) -> u16 {
         self.dither_cache[Self::calc_dither_cache_index(old_pixel, x, y)]
     }
 
-    pub fn dither_image(&mut self, input_image: &DynamicImage) -> GrayImage {
-        assert_eq!(
-            Self::calc_dither_cache_len(input_image.width(), input_image.height()),
-            self.dither_cache.len()
+    /// Pixels with a treatment other than blue noise become fully dark or bright
+    pub fn dither_image(
+        &mut self,
+        input_image: &DynamicImage,
+        treatments: Option<&[Treatment]>,
+    ) -> GrayImage {
+        // Can be less than 320x200 (e.g. without the status bar)
+        assert!(
+            Self::calc_dither_cache_len(input_image.width(), input_image.height())
+                <= self.dither_cache.len()
         );
 
-        // RgbImage == ImageBuffer<Rgb<u8>, Vec<u8>>
         let start = std::time::Instant::now();
-        // Grayscale image
-        //let old_img = libremarkable::image::imageops::grayscale(input_image);
-        // No need for srgb correction.
-        let old_img = libremarkable::image::GrayImage::from_fn(
-            input_image.width(),
-            input_image.height(),
-            |x, y| {
-                let pixel = input_image.get_pixel(x, y);
-                /*let r = pixel.data[0] as f32 / 255.0 * 1.5;
-                let g = pixel.data[1] as f32 / 255.0 * 1.5;
-                let b = pixel.data[2] as f32 / 255.0 * 1.5;
-                let gray = (1f32.min((r + g + b) / 3.0) * 255f32) as u8;
-                Luma([gray])*/
-
-                let r = pixel.0[0] as u16;
-                let g = pixel.0[1] as u16;
-                let b = pixel.0[2] as u16;
-                Luma([((r + g + b) / 3) as u8])
-            },
-        );
+        let mut old_img = grayscale(input_image);
         debug!("Dither: Grayscaling took {:?}", start.elapsed());
+        if let Some(preprocessing) = &self.preprocessing {
+            let start = std::time::Instant::now();
+            preprocess::apply(&mut old_img, preprocessing);
+            debug!("Dither: Preprocessing took {:?}", start.elapsed());
+        }
 
         let (width, height) = old_img.dimensions();
+        match self.scale {
+            RenderScale::X2 => self.expand_blocks::<2>(&old_img, treatments, width, height),
+            RenderScale::X3 => self.expand_blocks::<3>(&old_img, treatments, width, height),
+            RenderScale::X4 => self.expand_blocks::<4>(&old_img, treatments, width, height),
+        }
+    }
 
-        //let mut new_img = GrayImage::new(width * 4, height * 4);
-        let mut new_img_vec = vec![0u8; (width as usize * 4) * (height as usize * 4)];
+    /// Writes the cached block of every pixel. The scale is a const
+    /// so the inner loops get unrolled.
+    fn expand_blocks<const SCALE: usize>(
+        &self,
+        old_img: &GrayImage,
+        treatments: Option<&[Treatment]>,
+        width: u32,
+        height: u32,
+    ) -> GrayImage {
+        let new_width = width as usize * SCALE;
+        let mut new_img_vec = vec![0u8; new_width * (height as usize * SCALE)];
 
         // Using such a naive loop without any additions makes the code about 30% faster!
         let mut i_scaled = 0;
@@ -91,35 +145,126 @@ impl CachedDither4X {
                 }
 
                 let old_pixel = old_img.get_pixel(x, y);
-                let res = self.get_dithered_pixels_4x4(old_pixel, x, y);
-                new_img_vec[i_scaled + 0] = ((res >> 00) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled + 1] = ((res >> 01) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled + 2] = ((res >> 02) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled + 3] = ((res >> 03) & 0x1) as u8 * 255;
-                let i_scaled_nextline = i_scaled + (width as usize * 4);
-                new_img_vec[i_scaled_nextline + 0] = ((res >> 04) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 1] = ((res >> 05) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 2] = ((res >> 06) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 3] = ((res >> 07) & 0x1) as u8 * 255;
-                let i_scaled_nextline = i_scaled_nextline + (width as usize * 4);
-                new_img_vec[i_scaled_nextline + 0] = ((res >> 08) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 1] = ((res >> 09) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 2] = ((res >> 10) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 3] = ((res >> 11) & 0x1) as u8 * 255;
-                let i_scaled_nextline = i_scaled_nextline + (width as usize * 4);
-                new_img_vec[i_scaled_nextline + 0] = ((res >> 12) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 1] = ((res >> 13) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 2] = ((res >> 14) & 0x1) as u8 * 255;
-                new_img_vec[i_scaled_nextline + 3] = ((res >> 15) & 0x1) as u8 * 255;
+                let res = match treatments.map(|t| t[(y * width + x) as usize]) {
+                    Some(Treatment::Dark) => 0,
+                    Some(Treatment::Bright) => u16::MAX,
+                    _ => self.get_dithered_pixels(old_pixel, x, y),
+                };
+                for y_offset in 0..SCALE {
+                    let i_line = i_scaled + y_offset * new_width;
+                    for x_offset in 0..SCALE {
+                        new_img_vec[i_line + x_offset] =
+                            ((res >> (y_offset * SCALE + x_offset)) & 0x1) as u8 * 255;
+                    }
+                }
 
                 x += 1;
-                i_scaled += 4;
+                i_scaled += SCALE;
             }
 
             y += 1;
-            i_scaled += width as usize * (4 * 3);
+            i_scaled += new_width * (SCALE - 1);
+        }
+
+        GrayImage::from_vec(
+            new_width as u32,
+            (height as usize * SCALE) as u32,
+            new_img_vec,
+        )
+        .unwrap()
+    }
+}
+
+/// Dithers at the resolution of the panel, so the game can be scaled to any size
+/// (e.g. filling the display in fullscreen) instead of only multiples of 320x200.
+/// Uses the same noise and brightness boost as the cached dither in build/.
+pub struct ResampledDither {
+    noise: GrayImage,
+    /// Brightened luma of every gray value (1.5x like build/blue_noise_calculator.rs)
+    boosted: [u8; 256],
+    /// Source column of each output column and source row of each output row
+    x_map: Vec<u32>,
+    y_map: Vec<u32>,
+    preprocessing: Option<preprocess::Settings>,
+}
+
+impl ResampledDither {
+    pub fn new() -> Self {
+        let noise = libremarkable::image::load_from_memory(include_bytes!("../build/noise.png"))
+            .expect("Load noise.png")
+            .to_luma8();
+        let mut boosted = [0u8; 256];
+        for (luma, boosted) in boosted.iter_mut().enumerate() {
+            *boosted = (((luma as f32 / 255.0) * 1.5) * 255.0) as u8;
+        }
+        Self {
+            noise,
+            boosted,
+            x_map: vec![],
+            y_map: vec![],
+            preprocessing: None,
+        }
+    }
+
+    pub fn set_preprocessing(&mut self, preprocessing: Option<preprocess::Settings>) {
+        self.preprocessing = preprocessing;
+    }
+
+    /// Nearest neighbour, so the pixels stay sharp
+    fn update_maps(&mut self, source: (u32, u32), target: (u32, u32)) {
+        if self.x_map.len() != target.0 as usize || self.y_map.len() != target.1 as usize {
+            self.x_map = (0..target.0).map(|x| x * source.0 / target.0).collect();
+            self.y_map = (0..target.1).map(|y| y * source.1 / target.1).collect();
+        }
+    }
+
+    /// Pixels with a treatment other than blue noise become fully dark or bright
+    pub fn dither_image(
+        &mut self,
+        input_image: &DynamicImage,
+        treatments: Option<&[Treatment]>,
+        width: u32,
+        height: u32,
+    ) -> GrayImage {
+        let start = std::time::Instant::now();
+        let mut old_img = grayscale(input_image);
+        debug!("Dither: Grayscaling took {:?}", start.elapsed());
+        if let Some(preprocessing) = &self.preprocessing {
+            let start = std::time::Instant::now();
+            preprocess::apply(&mut old_img, preprocessing);
+            debug!("Dither: Preprocessing took {:?}", start.elapsed());
+        }
+
+        self.update_maps(old_img.dimensions(), (width, height));
+        let (noise_width, noise_height) =
+            (self.noise.width() as usize, self.noise.height() as usize);
+        let noise = self.noise.as_raw();
+        let old_width = old_img.width() as usize;
+        let old = old_img.as_raw();
+
+        let mut new_img_vec = vec![0u8; width as usize * height as usize];
+        for (y, new_line) in new_img_vec.chunks_exact_mut(width as usize).enumerate() {
+            let old_line_start = self.y_map[y] as usize * old_width;
+            let old_line = &old[old_line_start..][..old_width];
+            let noise_line = &noise[(y % noise_height) * noise_width..][..noise_width];
+            let mut noise_x = 0;
+            for (x, new_pixel) in new_line.iter_mut().enumerate() {
+                let old_x = self.x_map[x] as usize;
+                let bright = match treatments.map(|t| t[old_line_start + old_x]) {
+                    Some(Treatment::Dark) => false,
+                    Some(Treatment::Bright) => true,
+                    _ => self.boosted[old_line[old_x] as usize] > noise_line[noise_x],
+                };
+                if bright {
+                    *new_pixel = 255;
+                }
+                noise_x += 1;
+                if noise_x == noise_width {
+                    noise_x = 0;
+                }
+            }
         }
 
-        GrayImage::from_vec(width * 4, height * 4, new_img_vec).unwrap()
+        GrayImage::from_vec(width, height, new_img_vec).unwrap()
     }
 }
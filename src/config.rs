@@ -0,0 +1,521 @@
+//! Persistent user settings, stored as simple `key = value` lines
+
+use crate::battery;
+use crate::doom_hud::DoomStatusBar;
+use crate::evdev_keyboard::GrabMode;
+use crate::governor::FpsLimit;
+use crate::layout::KeyboardVariant;
+use crate::low_battery;
+use crate::macros::{self, Macro};
+use crate::preprocess;
+use crate::render_scale::{FullscreenScale, RenderScale};
+use crate::rotation::Rotation;
+use crate::shutdown::ExitScreen;
+use crate::waveform;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+const CONFIG_PATH: &str = "/home/root/.config/doomarkable/doomarkable.conf";
+
+pub static CONFIG: LazyLock<Mutex<Config>> = LazyLock::new(|| Mutex::new(Config::load()));
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Rotation of the normal (portrait) ui. Either `None` or `UpsideDown`.
+    pub portrait_rotation: Rotation,
+    /// Rotation of the game while in fullscreen. Either `Clockwise` or `CounterClockwise`.
+    pub fullscreen_rotation: Rotation,
+    /// Swap the sides of the movement and action buttons
+    pub left_handed: bool,
+    /// Pause the game while menus (settings, keyboard, ...) are shown
+    pub auto_pause: bool,
+    /// Key arrangement of the on-screen keyboard
+    pub keyboard_variant: KeyboardVariant,
+    /// Time a button needs to be held to trigger its long press action
+    pub long_press_ms: u64,
+    /// Max time between releasing and pressing a button again to count as double tap
+    pub double_tap_ms: u64,
+    /// Time a repeating button needs to be held before it starts repeating
+    pub repeat_delay_ms: u64,
+    /// Time between repeated presses of a held repeating button
+    pub repeat_interval_ms: u64,
+    /// Additional macros for the cheats layout (one `macro = Name: keys` line each)
+    pub macros: Vec<Macro>,
+    /// When to grab external keyboards, so other processes don't receive their keys
+    pub grab_keyboards: GrabMode,
+    /// Only grab these keyboards if not empty (device name or path, one `grab_allow` line each)
+    pub grab_allow: Vec<String>,
+    /// Never grab these keyboards (device name or path, one `grab_deny` line each)
+    pub grab_deny: Vec<String>,
+    /// Input devices to ignore (device name or path, one `disabled_device` line each)
+    pub disabled_devices: Vec<String>,
+    /// Key that opens the settings from anywhere (`none` to disable)
+    pub settings_hotkey: Option<u8>,
+    /// Save into a reserved slot before exiting and when the battery runs low
+    pub autosave: bool,
+    /// Battery percentages at which to warn, to pause and autosave and to shut down (0 to disable)
+    pub battery_warning_percentage: u8,
+    pub battery_save_percentage: u8,
+    pub battery_shutdown_percentage: u8,
+    /// Clean refresh of the game every this many frames and seconds (0 to disable)
+    pub ghost_cleanup_frames: u32,
+    pub ghost_cleanup_seconds: u32,
+    /// Clean refresh when at least this percentage of the game changed (0 to disable)
+    pub scene_change_percentage: u8,
+    /// Size of the game as a multiple of 320x200 (2, 3 or 4)
+    pub render_scale: RenderScale,
+    /// Size of the game in fullscreen: `blocks` (like portrait, with controls),
+    /// `integer` or `fit` (covering the controls, for playing with a keyboard)
+    pub fullscreen_scale: FullscreenScale,
+    /// Stretch the game to doom's original 4:3 when resampling for fullscreen
+    pub aspect_correction: bool,
+    /// Sharpen and brighten the game before dithering (see the `preprocess_*` keys)
+    pub preprocess: bool,
+    /// Strength of the sharpening in percent (0 to disable)
+    pub preprocess_sharpen_percentage: u8,
+    /// How much local contrast may be boosted, e.g. 2.0 (0 to disable)
+    pub preprocess_local_contrast: f32,
+    /// Darkest gray (0-255), so dark areas don't turn completely black
+    pub preprocess_darkness_floor: u8,
+    /// Plain black and white instead of blue noise for the status bar, automap and menus
+    pub region_dithering: bool,
+    /// Outline the areas detected for `region_dithering` (for debugging)
+    pub region_outlines: bool,
+    /// Where doom's status bar is shown in portrait: `game`, `both` or `enlarged`
+    /// (in place of the title, cut off from the game)
+    pub doom_status_bar: DoomStatusBar,
+    /// Max frame rate, or `auto` to follow how fast the display can refresh
+    pub fps_limit: FpsLimit,
+    /// Show frame rate and timings of the rendering below the game
+    pub perf_overlay: bool,
+    /// Directory with the power supplies in sysfs (can point to fake files for testing)
+    pub power_supply_root: String,
+    /// Whether the screen gets cleared or shows a goodbye message after exiting
+    pub exit_screen: ExitScreen,
+    /// Shell command to run after exiting, e.g. `systemctl start xochitl` (empty for none)
+    pub post_exit_command: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            portrait_rotation: Rotation::None,
+            fullscreen_rotation: Rotation::Clockwise,
+            left_handed: false,
+            auto_pause: true,
+            keyboard_variant: KeyboardVariant::Qwerty,
+            long_press_ms: 500,
+            double_tap_ms: 300,
+            repeat_delay_ms: 400,
+            repeat_interval_ms: 120,
+            macros: vec![],
+            grab_keyboards: GrabMode::Off,
+            grab_allow: vec![],
+            grab_deny: vec![],
+            disabled_devices: vec![],
+            settings_hotkey: macros::named_key("f12"),
+            autosave: false,
+            battery_warning_percentage: 20,
+            battery_save_percentage: 10,
+            battery_shutdown_percentage: 5,
+            ghost_cleanup_frames: 500,
+            ghost_cleanup_seconds: 60,
+            scene_change_percentage: 50,
+            render_scale: RenderScale::X4,
+            fullscreen_scale: FullscreenScale::Blocks,
+            aspect_correction: true,
+            preprocess: false,
+            preprocess_sharpen_percentage: 50,
+            preprocess_local_contrast: 2.0,
+            preprocess_darkness_floor: 24,
+            region_dithering: false,
+            region_outlines: false,
+            doom_status_bar: DoomStatusBar::InGame,
+            fps_limit: FpsLimit::Auto,
+            perf_overlay: false,
+            power_supply_root: battery::DEFAULT_POWER_SUPPLY_ROOT.to_owned(),
+            exit_screen: ExitScreen::Clear,
+            post_exit_command: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        let content = match std::fs::read_to_string(CONFIG_PATH) {
+            Ok(content) => content,
+            Err(err) => {
+                info!("No config loaded from {CONFIG_PATH:?} ({err}). Using defaults.");
+                return config;
+            }
+        };
+
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    warn!(
+                        "Config line {} is not of the form \"key = value\"",
+                        line_no + 1
+                    );
+                    continue;
+                }
+            };
+            if let Err(err) = config.set(key, value) {
+                warn!("Config line {}: {}", line_no + 1, err);
+            }
+        }
+
+        config
+    }
+
+    pub fn save(&self) {
+        let mut content = String::new();
+        for (key, value) in self.entries() {
+            content.push_str(&format!("{key} = {value}\n"));
+        }
+
+        if let Some(parent) = Path::new(CONFIG_PATH).parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+        if let Err(err) = std::fs::write(CONFIG_PATH, content) {
+            error!("Failed to save config to {CONFIG_PATH:?}: {err}");
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "portrait_rotation" => match parse_rotation(value)? {
+                rotation @ (Rotation::None | Rotation::UpsideDown) => {
+                    self.portrait_rotation = rotation
+                }
+                _ => return Err(format!("{value:?} is not a portrait rotation")),
+            },
+            "fullscreen_rotation" => match parse_rotation(value)? {
+                rotation @ (Rotation::Clockwise | Rotation::CounterClockwise) => {
+                    self.fullscreen_rotation = rotation
+                }
+                _ => return Err(format!("{value:?} is not a landscape rotation")),
+            },
+            "left_handed" => self.left_handed = parse_bool(value)?,
+            "auto_pause" => self.auto_pause = parse_bool(value)?,
+            "keyboard_variant" => self.keyboard_variant = parse_keyboard_variant(value)?,
+            "long_press_ms" => self.long_press_ms = parse_number(value)?,
+            "double_tap_ms" => self.double_tap_ms = parse_number(value)?,
+            "repeat_delay_ms" => self.repeat_delay_ms = parse_number(value)?,
+            "repeat_interval_ms" => self.repeat_interval_ms = parse_number(value)?,
+            "macro" => self.macros.push(Macro::parse(value)?),
+            "grab_keyboards" => self.grab_keyboards = parse_grab_mode(value)?,
+            "grab_allow" => self.grab_allow.push(value.to_owned()),
+            "grab_deny" => self.grab_deny.push(value.to_owned()),
+            "disabled_device" => self.disabled_devices.push(value.to_owned()),
+            "settings_hotkey" => self.settings_hotkey = parse_hotkey(value)?,
+            "autosave" => self.autosave = parse_bool(value)?,
+            "battery_warning_percentage" => self.battery_warning_percentage = parse_number(value)?,
+            "battery_save_percentage" => self.battery_save_percentage = parse_number(value)?,
+            "battery_shutdown_percentage" => {
+                self.battery_shutdown_percentage = parse_number(value)?
+            }
+            "ghost_cleanup_frames" => self.ghost_cleanup_frames = parse_number(value)?,
+            "ghost_cleanup_seconds" => self.ghost_cleanup_seconds = parse_number(value)?,
+            "scene_change_percentage" => self.scene_change_percentage = parse_number(value)?,
+            "render_scale" => self.render_scale = parse_render_scale(value)?,
+            "fullscreen_scale" => self.fullscreen_scale = parse_fullscreen_scale(value)?,
+            "aspect_correction" => self.aspect_correction = parse_bool(value)?,
+            "preprocess" => self.preprocess = parse_bool(value)?,
+            "preprocess_sharpen_percentage" => {
+                self.preprocess_sharpen_percentage = parse_number(value)?
+            }
+            "preprocess_local_contrast" => self.preprocess_local_contrast = parse_number(value)?,
+            "preprocess_darkness_floor" => self.preprocess_darkness_floor = parse_number(value)?,
+            "region_dithering" => self.region_dithering = parse_bool(value)?,
+            "region_outlines" => self.region_outlines = parse_bool(value)?,
+            "doom_status_bar" => self.doom_status_bar = parse_doom_status_bar(value)?,
+            "fps_limit" => self.fps_limit = parse_fps_limit(value)?,
+            "perf_overlay" => self.perf_overlay = parse_bool(value)?,
+            "power_supply_root" => self.power_supply_root = value.to_owned(),
+            "exit_screen" => self.exit_screen = parse_exit_screen(value)?,
+            "post_exit_command" => {
+                self.post_exit_command = Some(value.to_owned()).filter(|value| !value.is_empty())
+            }
+            _ => return Err(format!("Unknown key {key:?}")),
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = vec![
+            (
+                "portrait_rotation",
+                rotation_name(self.portrait_rotation).to_owned(),
+            ),
+            (
+                "fullscreen_rotation",
+                rotation_name(self.fullscreen_rotation).to_owned(),
+            ),
+            ("left_handed", self.left_handed.to_string()),
+            ("auto_pause", self.auto_pause.to_string()),
+            (
+                "keyboard_variant",
+                self.keyboard_variant.name().to_ascii_lowercase(),
+            ),
+            ("long_press_ms", self.long_press_ms.to_string()),
+            ("double_tap_ms", self.double_tap_ms.to_string()),
+            ("repeat_delay_ms", self.repeat_delay_ms.to_string()),
+            ("repeat_interval_ms", self.repeat_interval_ms.to_string()),
+            (
+                "grab_keyboards",
+                grab_mode_name(self.grab_keyboards).to_owned(),
+            ),
+            ("settings_hotkey", hotkey_name(self.settings_hotkey)),
+            ("autosave", self.autosave.to_string()),
+            (
+                "battery_warning_percentage",
+                self.battery_warning_percentage.to_string(),
+            ),
+            (
+                "battery_save_percentage",
+                self.battery_save_percentage.to_string(),
+            ),
+            (
+                "battery_shutdown_percentage",
+                self.battery_shutdown_percentage.to_string(),
+            ),
+            (
+                "ghost_cleanup_frames",
+                self.ghost_cleanup_frames.to_string(),
+            ),
+            (
+                "ghost_cleanup_seconds",
+                self.ghost_cleanup_seconds.to_string(),
+            ),
+            (
+                "scene_change_percentage",
+                self.scene_change_percentage.to_string(),
+            ),
+            ("render_scale", self.render_scale.factor().to_string()),
+            ("fullscreen_scale", self.fullscreen_scale.name().to_owned()),
+            ("aspect_correction", self.aspect_correction.to_string()),
+            ("preprocess", self.preprocess.to_string()),
+            (
+                "preprocess_sharpen_percentage",
+                self.preprocess_sharpen_percentage.to_string(),
+            ),
+            (
+                "preprocess_local_contrast",
+                self.preprocess_local_contrast.to_string(),
+            ),
+            (
+                "preprocess_darkness_floor",
+                self.preprocess_darkness_floor.to_string(),
+            ),
+            ("region_dithering", self.region_dithering.to_string()),
+            ("region_outlines", self.region_outlines.to_string()),
+            ("doom_status_bar", self.doom_status_bar.name().to_owned()),
+            ("fps_limit", self.fps_limit.name()),
+            ("perf_overlay", self.perf_overlay.to_string()),
+            ("power_supply_root", self.power_supply_root.clone()),
+            ("exit_screen", self.exit_screen.name().to_owned()),
+            (
+                "post_exit_command",
+                self.post_exit_command.clone().unwrap_or_default(),
+            ),
+        ];
+        for macro_ in &self.macros {
+            entries.push(("macro", macro_.to_definition()));
+        }
+        for device in &self.grab_allow {
+            entries.push(("grab_allow", device.clone()));
+        }
+        for device in &self.grab_deny {
+            entries.push(("grab_deny", device.clone()));
+        }
+        for device in &self.disabled_devices {
+            entries.push(("disabled_device", device.clone()));
+        }
+        entries
+    }
+
+    pub fn ghost_cleanup(&self) -> waveform::CleanupSettings {
+        waveform::CleanupSettings {
+            every_frames: self.ghost_cleanup_frames,
+            every_seconds: self.ghost_cleanup_seconds,
+            scene_change_percentage: self.scene_change_percentage,
+        }
+    }
+
+    /// Settings of the preprocessing (regardless of whether it's enabled)
+    pub fn preprocessing(&self) -> preprocess::Settings {
+        preprocess::Settings {
+            sharpen_percentage: self.preprocess_sharpen_percentage,
+            local_contrast_limit: self.preprocess_local_contrast,
+            darkness_floor: self.preprocess_darkness_floor,
+        }
+    }
+
+    pub fn battery_thresholds(&self) -> low_battery::Thresholds {
+        low_battery::Thresholds {
+            warn: self.battery_warning_percentage,
+            save: self.battery_save_percentage,
+            shutdown: self.battery_shutdown_percentage,
+        }
+    }
+
+    /// Whether the allow and deny lists permit grabbing this keyboard
+    pub fn may_grab_keyboard(&self, name: &str, path: &Path) -> bool {
+        let matches = |device: &String| device_matches(device, name, path);
+        !self.grab_deny.iter().any(matches)
+            && (self.grab_allow.is_empty() || self.grab_allow.iter().any(matches))
+    }
+
+    pub fn is_device_enabled(&self, name: &str, path: &Path) -> bool {
+        !self
+            .disabled_devices
+            .iter()
+            .any(|device| device_matches(device, name, path))
+    }
+
+    pub fn set_device_enabled(&mut self, name: &str, path: &Path, enabled: bool) {
+        if enabled {
+            self.disabled_devices
+                .retain(|device| !device_matches(device, name, path));
+        } else if self.is_device_enabled(name, path) {
+            self.disabled_devices.push(name.to_owned());
+        }
+    }
+}
+
+/// Devices in the config are either given by name or by path
+fn device_matches(device: &str, name: &str, path: &Path) -> bool {
+    device == name || Path::new(device) == path
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("Expected true or false, got {value:?}")),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Expected a number, got {value:?}"))
+}
+
+fn rotation_name(rotation: Rotation) -> &'static str {
+    match rotation {
+        Rotation::None => "none",
+        Rotation::Clockwise => "clockwise",
+        Rotation::UpsideDown => "upside_down",
+        Rotation::CounterClockwise => "counter_clockwise",
+    }
+}
+
+fn parse_rotation(value: &str) -> Result<Rotation, String> {
+    Ok(match value {
+        "none" => Rotation::None,
+        "clockwise" => Rotation::Clockwise,
+        "upside_down" => Rotation::UpsideDown,
+        "counter_clockwise" => Rotation::CounterClockwise,
+        _ => return Err(format!("Unknown rotation {value:?}")),
+    })
+}
+
+fn grab_mode_name(mode: GrabMode) -> &'static str {
+    match mode {
+        GrabMode::Off => "off",
+        GrabMode::Fullscreen => "fullscreen",
+        GrabMode::Always => "always",
+    }
+}
+
+fn parse_grab_mode(value: &str) -> Result<GrabMode, String> {
+    Ok(match value {
+        "off" => GrabMode::Off,
+        "fullscreen" => GrabMode::Fullscreen,
+        "always" => GrabMode::Always,
+        _ => return Err(format!("Unknown grab mode {value:?}")),
+    })
+}
+
+fn hotkey_name(hotkey: Option<u8>) -> String {
+    match hotkey {
+        None => "none".to_owned(),
+        Some(key) => match macros::key_name(key) {
+            Some(name) => name.to_owned(),
+            None => (key as char).to_string(),
+        },
+    }
+}
+
+/// Either `none`, a key name like in macros (`f12`) or a single character
+fn parse_hotkey(value: &str) -> Result<Option<u8>, String> {
+    let value = value.to_ascii_lowercase();
+    if value == "none" {
+        return Ok(None);
+    }
+    if let Some(key) = macros::named_key(&value) {
+        return Ok(Some(key));
+    }
+    match value.as_bytes() {
+        [key] if key.is_ascii_graphic() => Ok(Some(*key)),
+        _ => Err(format!("Unknown key {value:?}")),
+    }
+}
+
+fn parse_keyboard_variant(value: &str) -> Result<KeyboardVariant, String> {
+    Ok(match value {
+        "qwerty" => KeyboardVariant::Qwerty,
+        "qwertz" => KeyboardVariant::Qwertz,
+        "azerty" => KeyboardVariant::Azerty,
+        _ => return Err(format!("Unknown keyboard variant {value:?}")),
+    })
+}
+
+fn parse_exit_screen(value: &str) -> Result<ExitScreen, String> {
+    Ok(match value {
+        "clear" => ExitScreen::Clear,
+        "goodbye" => ExitScreen::Goodbye,
+        _ => return Err(format!("Unknown exit screen {value:?}")),
+    })
+}
+
+fn parse_render_scale(value: &str) -> Result<RenderScale, String> {
+    RenderScale::from_factor(parse_number(value)?)
+        .ok_or_else(|| format!("Unsupported render scale {value:?} (2, 3 or 4)"))
+}
+
+fn parse_fullscreen_scale(value: &str) -> Result<FullscreenScale, String> {
+    Ok(match value {
+        "blocks" => FullscreenScale::Blocks,
+        "integer" => FullscreenScale::Integer,
+        "fit" => FullscreenScale::Fit,
+        _ => return Err(format!("Unknown fullscreen scale {value:?}")),
+    })
+}
+
+fn parse_doom_status_bar(value: &str) -> Result<DoomStatusBar, String> {
+    Ok(match value {
+        "game" => DoomStatusBar::InGame,
+        "both" => DoomStatusBar::Both,
+        "enlarged" => DoomStatusBar::Enlarged,
+        _ => return Err(format!("Unknown doom status bar placement {value:?}")),
+    })
+}
+
+fn parse_fps_limit(value: &str) -> Result<FpsLimit, String> {
+    match value {
+        "auto" => Ok(FpsLimit::Auto),
+        _ => match parse_number(value)? {
+            0 => Err("The fps limit must be above 0".to_owned()),
+            fps => Ok(FpsLimit::Fixed(fps)),
+        },
+    }
+}
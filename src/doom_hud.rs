@@ -0,0 +1,116 @@
+//! Doom's own status bar (health, ammo, armor, keys), enlarged into the area of the
+//! title in portrait. It's dithered with a plain threshold, so the numbers stay crisp,
+//! and only gets refreshed when it changed.
+
+use crate::regions;
+use crate::render_scale::{SOURCE_HEIGHT, SOURCE_WIDTH};
+use crate::rotation::Rotation;
+use libremarkable::cgmath::Point2;
+use libremarkable::framebuffer::core::Framebuffer;
+use libremarkable::framebuffer::{common, FramebufferRefresh, PartialRefreshMode};
+use libremarkable::image::{GrayImage, RgbImage};
+
+/// Rows of the frame (at 320x200) the status bar takes
+pub const SOURCE_ROWS: u32 = 32;
+/// Logical (portrait) area of the title. 1404 / 320 also scales the 32 rows to 140.
+const PANEL_RECT: common::mxcfb_rect = common::mxcfb_rect {
+    left: 0,
+    top: 62,
+    width: 1404,
+    height: 140,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoomStatusBar {
+    /// Only inside the game (default)
+    InGame,
+    /// Inside the game and enlarged
+    Both,
+    /// Only enlarged. The game gets cut off above it.
+    Enlarged,
+}
+
+impl DoomStatusBar {
+    pub fn name(self) -> &'static str {
+        match self {
+            DoomStatusBar::InGame => "game",
+            DoomStatusBar::Both => "both",
+            DoomStatusBar::Enlarged => "enlarged",
+        }
+    }
+
+    /// Whether the panel takes the place of the title
+    pub fn shows_panel(self) -> bool {
+        self != DoomStatusBar::InGame
+    }
+
+    /// Height of the frame to show as the game
+    pub fn game_rows(self) -> u32 {
+        match self {
+            DoomStatusBar::Enlarged => SOURCE_HEIGHT - SOURCE_ROWS,
+            _ => SOURCE_HEIGHT,
+        }
+    }
+}
+
+pub struct HudPanel {
+    /// Last drawn panel, to skip refreshes when nothing changed
+    previous: Option<GrayImage>,
+}
+
+impl HudPanel {
+    pub fn new() -> Self {
+        Self { previous: None }
+    }
+
+    /// Draw again on the next call (e.g. after the screen got cleared)
+    pub fn invalidate(&mut self) {
+        self.previous = None;
+    }
+
+    fn dither(frame: &RgbImage) -> GrayImage {
+        let top = SOURCE_HEIGHT - SOURCE_ROWS;
+        GrayImage::from_fn(PANEL_RECT.width, PANEL_RECT.height, |x, y| {
+            let pixel = frame.get_pixel(
+                x * SOURCE_WIDTH / PANEL_RECT.width,
+                top + y * SOURCE_ROWS / PANEL_RECT.height,
+            );
+            libremarkable::image::Luma([if regions::is_bright(pixel) { 255 } else { 0 }])
+        })
+    }
+
+    /// Takes the downscaled (320x200) frame. Only draws and refreshes if the bar changed.
+    pub fn draw(
+        &mut self,
+        fb: &mut Framebuffer,
+        frame: &RgbImage,
+        gray_to_native: &[(u8, u8); 256],
+        rotation: Rotation,
+    ) {
+        let panel = Self::dither(frame);
+        if self.previous.as_ref() == Some(&panel) {
+            return;
+        }
+
+        let pos = Point2 {
+            x: PANEL_RECT.left as i32,
+            y: PANEL_RECT.top as i32,
+        };
+        let screen_rect = if rotation != Rotation::None {
+            crate::draw_image_mono_rotated(fb, pos, &panel, gray_to_native, rotation)
+        } else {
+            crate::draw_image_mono(fb, pos, &panel, gray_to_native)
+        };
+        // Only black and white, so the fast waveform has no downsides
+        fb.partial_refresh(
+            &screen_rect,
+            PartialRefreshMode::Async,
+            common::waveform_mode::WAVEFORM_MODE_DU,
+            common::display_temp::TEMP_USE_REMARKABLE_DRAW,
+            common::dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+            0,
+            false,
+        );
+        self.previous = Some(panel);
+    }
+}
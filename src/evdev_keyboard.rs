@@ -1,16 +1,163 @@
-use std::{path::Path, sync::mpsc::Sender};
+use std::{
+    os::fd::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, LazyLock, Mutex,
+    },
+};
 
-use doomgeneric::input::KeyData;
-use evdev::KeyCode;
+use doomgeneric::input::{keys, KeyData};
+use evdev::{AbsoluteAxisCode, KeyCode, RelativeAxisCode};
+
+use crate::config::CONFIG;
+use crate::layout::{KEY_MAP, KEY_RUN};
 
 const DEV_INPUT_DIR: &str = "/dev/input";
 
+/// `_IOW('E', 0x90, int)` from linux/input.h
+const EVIOCGRAB: u32 = 0x4004_4590;
+
+/// When keyboards get grabbed exclusively, so no other process receives their keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GrabMode {
+    #[default]
+    Off,
+    /// Only while the game is in fullscreen
+    Fullscreen,
+    /// As long as the game runs
+    Always,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    /// Full keyboard with letters
+    Keyboard,
+    /// Anything else with keys the game knows (numpads, macro pads, ...)
+    Keypad,
+    Gamepad,
+    Mouse,
+}
+
+impl DeviceKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceKind::Keyboard => "Keyboard",
+            DeviceKind::Keypad => "Keypad",
+            DeviceKind::Gamepad => "Gamepad",
+            DeviceKind::Mouse => "Mouse",
+        }
+    }
+}
+
+/// A connected device as shown in the ui
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub kind: DeviceKind,
+    pub enabled: bool,
+    pub grabbed: bool,
+}
+
+struct Device {
+    path: PathBuf,
+    name: String,
+    kind: DeviceKind,
+    fd: RawFd,
+    /// Shared with the thread of the device, which drops all events while disabled
+    enabled: Arc<AtomicBool>,
+    grabbed: bool,
+}
+
+/// Devices that are currently being listened to. A device thread removes its entry
+/// before the device gets closed, so the fds are valid as long as the lock is held.
+static DEVICES: LazyLock<Mutex<Vec<Device>>> = LazyLock::new(|| Mutex::new(vec![]));
+/// Whether devices (that the config allows) should currently be grabbed
+static GRAB_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Set when a device got connected or disconnected
+static DEVICES_CHANGED: AtomicBool = AtomicBool::new(false);
+
 pub fn init(keydata_tx: Sender<KeyData>) {
-    scan_for_existing_keyboards(&keydata_tx);
-    spawn_keyboard_watcher(keydata_tx);
+    scan_for_existing_devices(&keydata_tx);
+    spawn_device_watcher(keydata_tx);
 }
 
-fn scan_for_existing_keyboards(keydata_tx: &Sender<KeyData>) {
+/// All devices that are currently connected and used
+pub fn connected_devices() -> Vec<DeviceInfo> {
+    DEVICES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|device| DeviceInfo {
+            path: device.path.clone(),
+            name: device.name.clone(),
+            kind: device.kind,
+            enabled: device.enabled.load(Ordering::Relaxed),
+            grabbed: device.grabbed,
+        })
+        .collect()
+}
+
+/// Whether devices got connected or disconnected since the last call
+pub fn take_devices_changed() -> bool {
+    DEVICES_CHANGED.swap(false, Ordering::Relaxed)
+}
+
+/// Enable, disable, grab and release the devices according to the config.
+/// Devices only get grabbed if the [`GrabMode`] allows it.
+pub fn apply_config(fullscreen: bool) {
+    let active = match CONFIG.lock().unwrap().grab_keyboards {
+        GrabMode::Off => false,
+        GrabMode::Fullscreen => fullscreen,
+        GrabMode::Always => true,
+    };
+    set_grab_active(active);
+}
+
+/// Give all devices back to other processes (e.g. before exiting)
+pub fn release_grabs() {
+    set_grab_active(false);
+}
+
+fn set_grab_active(active: bool) {
+    GRAB_ACTIVE.store(active, Ordering::Relaxed);
+    let config = CONFIG.lock().unwrap();
+    for device in DEVICES.lock().unwrap().iter_mut() {
+        let enabled = config.is_device_enabled(&device.name, &device.path);
+        device.enabled.store(enabled, Ordering::Relaxed);
+        let grab = active && enabled && config.may_grab_keyboard(&device.name, &device.path);
+        set_grabbed(device, grab);
+    }
+}
+
+fn set_grabbed(device: &mut Device, grab: bool) {
+    if device.grabbed == grab {
+        return;
+    }
+    // Can't use evdev::Device::grab() here, since the device is owned by its
+    // thread which is usually blocked on reading events.
+    let result = unsafe { libc::ioctl(device.fd, EVIOCGRAB as _, grab as libc::c_int) };
+    if result < 0 {
+        warn!(
+            "Failed to {} device {} ({:?}): {}",
+            if grab { "grab" } else { "release" },
+            device.name,
+            device.path,
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+    device.grabbed = grab;
+    info!(
+        "{} device {}",
+        if grab { "Grabbed" } else { "Released" },
+        device.name
+    );
+}
+
+fn scan_for_existing_devices(keydata_tx: &Sender<KeyData>) {
     // Find existing unknown evdev devices in /dev/input
     for entry in std::fs::read_dir(DEV_INPUT_DIR).expect("Listing files of input devices dir") {
         let entry = match entry {
@@ -28,12 +175,12 @@ fn scan_for_existing_keyboards(keydata_tx: &Sender<KeyData>) {
             continue; // Skip directories or known input devices (gpio, mt, wacom)
         }
         debug!("Existing evdev device detected: {path:?}");
-        spawn_evdev_keyboard(path, keydata_tx.clone());
+        spawn_evdev_device(path, keydata_tx.clone());
     }
 }
 
-fn spawn_keyboard_watcher(keydata_tx: Sender<KeyData>) {
-    // Listen for new devices in /dev/input to allow hotplugging keyboards
+fn spawn_device_watcher(keydata_tx: Sender<KeyData>) {
+    // Listen for new devices in /dev/input to allow hotplugging keyboards, gamepads, etc.
     std::thread::spawn(move || {
         let mut inotify = match inotify::Inotify::init() {
             Ok(inotify) => {
@@ -57,7 +204,7 @@ fn spawn_keyboard_watcher(keydata_tx: Sender<KeyData>) {
             let inotify_events = match inotify.read_events_blocking(&mut inotify_buffer) {
                 Ok(events) => events,
                 Err(err) => {
-                    error!("Encountered an issue while reading inotify events! Keyboard hotplugging will not work anymore! {:?}", err);
+                    error!("Encountered an issue while reading inotify events! Device hotplugging will not work anymore! {:?}", err);
                     return;
                 }
             };
@@ -75,15 +222,118 @@ fn spawn_keyboard_watcher(keydata_tx: Sender<KeyData>) {
                 }
                 let path = Path::new(DEV_INPUT_DIR).join(filename);
                 debug!("New evdev device detected: {path:?}");
-                spawn_evdev_keyboard(path, keydata_tx.clone());
+                spawn_evdev_device(path, keydata_tx.clone());
             }
         }
     });
 }
 
-// Check if device is a keyboard, spawn new thread and listen for keystrokes and send them to keydata_tx
-fn spawn_evdev_keyboard(path: impl AsRef<Path>, keydata_tx: Sender<KeyData>) {
-    // Check if this device is a valid keyboard
+fn classify_device(device: &evdev::Device) -> Option<DeviceKind> {
+    let keys = device.supported_keys()?;
+    let has_keys = |codes: &[KeyCode]| codes.iter().all(|code| keys.contains(*code));
+
+    if has_keys(&[KeyCode::BTN_SOUTH]) || has_keys(&[KeyCode::BTN_TRIGGER]) {
+        Some(DeviceKind::Gamepad)
+    } else if has_keys(&[KeyCode::BTN_LEFT])
+        && device
+            .supported_relative_axes()
+            .map(|axes| axes.contains(RelativeAxisCode::REL_X))
+            .unwrap_or(false)
+    {
+        Some(DeviceKind::Mouse)
+    } else if has_keys(&[
+        KeyCode::KEY_Q,
+        KeyCode::KEY_W,
+        KeyCode::KEY_E,
+        KeyCode::KEY_R,
+        KeyCode::KEY_T,
+        KeyCode::KEY_Y,
+    ]) {
+        Some(DeviceKind::Keyboard)
+    } else if keys
+        .iter()
+        .any(|key| map_evdev_keycode_to_doom(key).is_some())
+    {
+        Some(DeviceKind::Keypad)
+    } else {
+        None
+    }
+}
+
+/// A stick or d-pad axis that presses one of two keys when pushed far enough
+struct Axis {
+    code: AbsoluteAxisCode,
+    negative_key: u8,
+    positive_key: u8,
+    center: i32,
+    threshold: i32,
+    /// Key currently pressed by this axis
+    pressed: Option<u8>,
+}
+
+impl Axis {
+    fn from_device(device: &evdev::Device) -> Vec<Axis> {
+        let absinfos = match device.get_absinfo() {
+            Ok(absinfos) => absinfos,
+            Err(_) => return vec![],
+        };
+        absinfos
+            .filter_map(|(code, info)| {
+                let (negative_key, positive_key) = match code {
+                    AbsoluteAxisCode::ABS_X | AbsoluteAxisCode::ABS_HAT0X => {
+                        (*keys::KEY_LEFT, *keys::KEY_RIGHT)
+                    }
+                    AbsoluteAxisCode::ABS_Y | AbsoluteAxisCode::ABS_HAT0Y => {
+                        (*keys::KEY_UP, *keys::KEY_DOWN)
+                    }
+                    _ => return None,
+                };
+                Some(Axis {
+                    code,
+                    negative_key,
+                    positive_key,
+                    center: (info.minimum() + info.maximum()) / 2,
+                    // Half way between center and the edge. 0 for d-pads (-1 to 1).
+                    threshold: (info.maximum() - info.minimum()) / 4,
+                    pressed: None,
+                })
+            })
+            .collect()
+    }
+
+    fn update(&mut self, value: i32) -> Vec<KeyData> {
+        let offset = value - self.center;
+        let key = if offset < -self.threshold {
+            Some(self.negative_key)
+        } else if offset > self.threshold {
+            Some(self.positive_key)
+        } else {
+            None
+        };
+        if key == self.pressed {
+            return vec![];
+        }
+
+        let mut keydata = vec![];
+        if let Some(released) = self.pressed {
+            keydata.push(KeyData {
+                key: released,
+                pressed: false,
+            });
+        }
+        if let Some(pressed) = key {
+            keydata.push(KeyData {
+                key: pressed,
+                pressed: true,
+            });
+        }
+        self.pressed = key;
+        keydata
+    }
+}
+
+// Classify the device, spawn new thread and listen for keys and send them to keydata_tx
+fn spawn_evdev_device(path: impl AsRef<Path>, keydata_tx: Sender<KeyData>) {
     let mut device = match evdev::Device::open(&path) {
         Ok(device) => device,
         Err(err) => {
@@ -91,71 +341,141 @@ fn spawn_evdev_keyboard(path: impl AsRef<Path>, keydata_tx: Sender<KeyData>) {
             return;
         }
     };
-    if !device.supported_events().contains(evdev::EventType::KEY)
-        || [
-            KeyCode::KEY_Q,
-            KeyCode::KEY_W,
-            KeyCode::KEY_E,
-            KeyCode::KEY_R,
-            KeyCode::KEY_T,
-            KeyCode::KEY_Y,
-        ]
-        .iter()
-        .any(|key| {
-            !device
-                .supported_keys()
-                .map(|keys| keys.contains(*key))
-                .unwrap_or(false)
-        })
-    {
-        info!("The evdev device {:?} is not a keyboard.", path.as_ref());
-        return;
-    }
+    let kind = match classify_device(&device) {
+        Some(kind) => kind,
+        None => {
+            info!(
+                "The evdev device {:?} is not usable for the game.",
+                path.as_ref()
+            );
+            return;
+        }
+    };
+    let mut axes = match kind {
+        DeviceKind::Gamepad => Axis::from_device(&device),
+        _ => vec![],
+    };
 
     let path = path.as_ref().to_path_buf();
     // Listen for keys in new thread
     std::thread::spawn(move || {
-        let name = device.name().unwrap().to_owned();
-        info!("Keyboard at {path:?} detected: {name}");
+        let name = device.name().unwrap_or("Unnamed device").to_owned();
+        info!("{} at {path:?} detected: {name}", kind.name());
+
+        let fd = device.as_raw_fd();
+        let enabled = Arc::new(AtomicBool::new(true));
+        {
+            let config = CONFIG.lock().unwrap();
+            enabled.store(config.is_device_enabled(&name, &path), Ordering::Relaxed);
+            let mut entry = Device {
+                path: path.clone(),
+                name: name.clone(),
+                kind,
+                fd,
+                enabled: enabled.clone(),
+                grabbed: false,
+            };
+            if GRAB_ACTIVE.load(Ordering::Relaxed)
+                && enabled.load(Ordering::Relaxed)
+                && config.may_grab_keyboard(&name, &path)
+            {
+                set_grabbed(&mut entry, true);
+            }
+            DEVICES.lock().unwrap().push(entry);
+        }
+        DEVICES_CHANGED.store(true, Ordering::Relaxed);
 
         loop {
             let evs: Vec<evdev::InputEvent> = match device.fetch_events() {
                 Ok(evs) => evs,
                 Err(err) => {
-                    debug!("Lost connection to keyboard {name} ({path:?}). It likely got disconnected. Error: {err}");
-                    info!("Keyboard disconnected: {name}");
+                    debug!("Lost connection to device {name} ({path:?}). It likely got disconnected. Error: {err}");
+                    info!("{} disconnected: {name}", kind.name());
+                    DEVICES.lock().unwrap().retain(|device| device.fd != fd);
+                    DEVICES_CHANGED.store(true, Ordering::Relaxed);
                     return;
                 }
             }.collect();
 
+            if !enabled.load(Ordering::Relaxed) {
+                continue;
+            }
+
             for ev in evs {
-                if let evdev::EventSummary::Key(_event, key, value) = ev.destructure() {
-                    if value != 0 && value != 1 {
-                        continue; // Ignore key being held (value == 2) and other potential values.
-                    }
+                match ev.destructure() {
+                    evdev::EventSummary::Key(_event, key, value) => {
+                        if value != 0 && value != 1 {
+                            continue; // Ignore key being held (value == 2) and other potential values.
+                        }
 
-                    if let Ok((keycode, scancodes)) = device.get_scancode_by_index(key.0) {
-                        debug!("{} ({key:?}, keycode: {keycode}, scancodes: {scancodes:?}) => {value}", key.0);
-                    } else {
-                        debug!("{} ({key:?}) => {value}", key.0);
-                    }
+                        if let Ok((keycode, scancodes)) = device.get_scancode_by_index(key.0) {
+                            debug!("{} ({key:?}, keycode: {keycode}, scancodes: {scancodes:?}) => {value}", key.0);
+                        } else {
+                            debug!("{} ({key:?}) => {value}", key.0);
+                        }
 
-                    if let Some(doom_key_code) = map_evdev_keycode_to_doom(key) {
-                        keydata_tx
-                            .send(KeyData {
-                                key: doom_key_code,
-                                pressed: value == 1,
-                            })
-                            .ok();
-                    } else {
-                        debug!("No mapping for key found. Last keypress is not forwarded to game.")
+                        let doom_key_code = match kind {
+                            DeviceKind::Keyboard | DeviceKind::Keypad => {
+                                map_evdev_keycode_to_doom(key)
+                            }
+                            DeviceKind::Gamepad => map_gamepad_button_to_doom(key),
+                            DeviceKind::Mouse => map_mouse_button_to_doom(key),
+                        };
+                        if let Some(doom_key_code) = doom_key_code {
+                            keydata_tx
+                                .send(KeyData {
+                                    key: doom_key_code,
+                                    pressed: value == 1,
+                                })
+                                .ok();
+                        } else {
+                            debug!(
+                                "No mapping for key found. Last keypress is not forwarded to game."
+                            )
+                        }
+                    }
+                    evdev::EventSummary::AbsoluteAxis(_event, code, value) => {
+                        if let Some(axis) = axes.iter_mut().find(|axis| axis.code == code) {
+                            for keydata in axis.update(value) {
+                                keydata_tx.send(keydata).ok();
+                            }
+                        }
                     }
+                    _ => {}
                 }
             }
         }
     });
 }
 
+fn map_gamepad_button_to_doom(key: KeyCode) -> Option<u8> {
+    Some(match key {
+        KeyCode::BTN_SOUTH | KeyCode::BTN_TRIGGER | KeyCode::BTN_TR2 => *keys::KEY_FIRE,
+        KeyCode::BTN_EAST | KeyCode::BTN_THUMB => *keys::KEY_USE,
+        KeyCode::BTN_WEST | KeyCode::BTN_TL2 => KEY_RUN,
+        KeyCode::BTN_NORTH => keys::KEY_ENTER,
+        KeyCode::BTN_TL => 0xa0, // Strafe left
+        KeyCode::BTN_TR => 0xa1, // Strafe right
+        KeyCode::BTN_START => keys::KEY_ESCAPE,
+        KeyCode::BTN_SELECT => KEY_MAP,
+        KeyCode::BTN_DPAD_UP => *keys::KEY_UP,
+        KeyCode::BTN_DPAD_DOWN => *keys::KEY_DOWN,
+        KeyCode::BTN_DPAD_LEFT => *keys::KEY_LEFT,
+        KeyCode::BTN_DPAD_RIGHT => *keys::KEY_RIGHT,
+        _ => return None,
+    })
+}
+
+/// Mouse movement is not used, since the game only receives keys
+fn map_mouse_button_to_doom(key: KeyCode) -> Option<u8> {
+    Some(match key {
+        KeyCode::BTN_LEFT => *keys::KEY_FIRE,
+        KeyCode::BTN_RIGHT => *keys::KEY_USE,
+        KeyCode::BTN_MIDDLE => *keys::KEY_STRAFE,
+        _ => return None,
+    })
+}
+
 fn map_evdev_keycode_to_doom(key: KeyCode) -> Option<u8> {
     // https://github.com/ozkl/doomgeneric/blob/613f870b6fa83ede448a247de5a2571092fa729d/doomgeneric/doomkeys.h
     Some(match key {
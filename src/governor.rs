@@ -0,0 +1,91 @@
+//! Frame rate governor. Instead of a fixed cap, frames are submitted as fast as the
+//! panel completes their refreshes. Before a frame gets submitted, the refresh of the
+//! previous one is waited for, so the EPDC queue never backs up, and the frame
+//! interval follows the measured completion times.
+
+use libremarkable::framebuffer::core::Framebuffer;
+use libremarkable::framebuffer::FramebufferRefresh;
+use std::time::{Duration, Instant};
+
+/// The game itself runs with 35 tics per second
+const MIN_INTERVAL: Duration = Duration::from_micros(1_000_000 / 35);
+const MAX_INTERVAL: Duration = Duration::from_secs(1);
+/// Waiting shorter than this means the refresh was already done
+const BLOCKED_THRESHOLD: Duration = Duration::from_millis(2);
+/// Leaves the panel some time for other refreshes (buttons, status bar)
+const HEADROOM: f32 = 1.1;
+/// Interval factor when the panel kept up
+const SPEEDUP: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpsLimit {
+    /// Adapt to the panel
+    Auto,
+    /// Submit at this rate without waiting for the panel
+    Fixed(u32),
+}
+
+impl FpsLimit {
+    pub fn name(self) -> String {
+        match self {
+            FpsLimit::Auto => "auto".to_owned(),
+            FpsLimit::Fixed(fps) => fps.to_string(),
+        }
+    }
+}
+
+pub struct Governor {
+    limit: FpsLimit,
+    interval: Duration,
+    /// Marker and submission time of the last frame
+    in_flight: Option<(u32, Instant)>,
+}
+
+impl Governor {
+    pub fn new(limit: FpsLimit, initial_interval: Duration) -> Self {
+        Self {
+            limit,
+            interval: initial_interval.clamp(MIN_INTERVAL, MAX_INTERVAL),
+            in_flight: None,
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: FpsLimit) {
+        self.limit = limit;
+    }
+
+    /// Time between two frames
+    pub fn frame_interval(&self) -> Duration {
+        match self.limit {
+            FpsLimit::Auto => self.interval,
+            FpsLimit::Fixed(fps) => Duration::from_micros(1_000_000 / fps.max(1) as u64),
+        }
+    }
+
+    /// Wait until the refresh of the previous frame completed and adapt the interval.
+    /// Any handle of the framebuffer works, so a separate one doesn't block others from
+    /// drawing meanwhile.
+    pub fn wait_for_previous(&mut self, fb: &Framebuffer) {
+        let (marker, submitted) = match self.in_flight.take() {
+            Some(in_flight) if self.limit == FpsLimit::Auto => in_flight,
+            _ => return,
+        };
+
+        let start = Instant::now();
+        fb.wait_refresh_complete(marker);
+        if start.elapsed() >= BLOCKED_THRESHOLD {
+            // The panel is slower than the frames come. Follow its completion time.
+            let completion = submitted.elapsed();
+            debug!("Refresh completed after {completion:?}");
+            self.interval = completion.mul_f32(HEADROOM);
+        } else {
+            // Already done. Try to go faster.
+            self.interval = self.interval.mul_f32(SPEEDUP);
+        }
+        self.interval = self.interval.clamp(MIN_INTERVAL, MAX_INTERVAL);
+    }
+
+    pub fn submitted(&mut self, marker: u32) {
+        self.in_flight = Some((marker, Instant::now()));
+    }
+}
@@ -0,0 +1,86 @@
+//! Runs the game without display and input devices, fed only by a recording (see
+//! [`recording::REPLAY_ENV`]). Once the recording ended and the game ran for another
+//! second, the last frame gets saved and the process exits. This reproduces input bugs
+//! off the device, and the frames of two runs can be compared.
+
+use crate::recording;
+use crate::saves;
+use libremarkable::image::RgbImage;
+use std::path::PathBuf;
+
+/// Env var with a path to save the last frame (PNG) to. Needs a recording to replay.
+pub const HEADLESS_ENV: &str = "DOOMARKABLE_HEADLESS";
+
+/// Doom runs at 35 tics per second
+const TICS_AFTER_REPLAY: u64 = 35;
+
+pub struct Output {
+    path: PathBuf,
+    /// Tics since the recording ended
+    tics_after_replay: u64,
+}
+
+impl Output {
+    /// Called by the game at the end of every tic
+    pub fn tic_ended(&mut self, replay_finished: bool, frame: &RgbImage) {
+        if !replay_finished {
+            return;
+        }
+        self.tics_after_replay += 1;
+        if self.tics_after_replay < TICS_AFTER_REPLAY {
+            return;
+        }
+        match frame.save(&self.path) {
+            Ok(()) => {
+                info!("Saved the last frame to {:?}", self.path);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                error!("Failed to save the last frame to {:?}: {err}", self.path);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+pub fn run(output: PathBuf) {
+    let path = match std::env::var_os(recording::REPLAY_ENV) {
+        Some(path) => path,
+        None => {
+            error!(
+                "{} needs a recording in {}",
+                HEADLESS_ENV,
+                recording::REPLAY_ENV
+            );
+            std::process::exit(1);
+        }
+    };
+    let replayer = match recording::Replayer::load(&path) {
+        Ok(replayer) => replayer,
+        Err(err) => {
+            error!("Failed to load input recording {path:?}: {err}");
+            std::process::exit(1);
+        }
+    };
+    // Off the device the saves directory might not exist. The game then runs in the
+    // current directory and needs -iwad.
+    if let Err(err) = saves::enter_saves_dir() {
+        warn!("Not using the saves directory: {err}");
+    }
+
+    info!("Replaying {path:?} headless");
+    let (_keydata_tx, keydata_rx) = std::sync::mpsc::channel();
+    doomgeneric::game::init(crate::Game {
+        image: std::sync::Arc::new(std::sync::Mutex::new(RgbImage::new(0, 0))),
+        keydata_receiver: keydata_rx,
+        tic: 0,
+        recorder: None,
+        replayer: Some(replayer),
+        headless: Some(Output {
+            path: output,
+            tics_after_replay: 0,
+        }),
+        held_keys: Default::default(),
+        last_key_tic: 0,
+    });
+}
@@ -0,0 +1,58 @@
+use super::{ButtonAction, Element, Layout, LayoutId};
+use crate::config::Config;
+use crate::macros;
+use libremarkable::framebuffer::common;
+
+const COLUMNS: u32 = 3;
+const ROWS: u32 = 5;
+
+pub fn create(config: &Config) -> Layout {
+    let mut buttons = vec![
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 100,
+                top: 1400 - 300 - 10 - 10,
+                width: 100,
+                height: 50,
+            },
+            label: "Back".into(),
+            label_size: 25.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Settings),
+        },
+        Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: "Cheats & macros".into(),
+            size: 100.0,
+        },
+    ];
+
+    let all_macros = macros::builtin_macros()
+        .into_iter()
+        .chain(config.macros.iter().cloned());
+    let width = (common::DISPLAYWIDTH as u32 - 62 * 2 - 10 * (COLUMNS - 1)) / COLUMNS;
+    for (i, macro_) in all_macros.enumerate() {
+        let (column, row) = (i as u32 % COLUMNS, i as u32 / COLUMNS);
+        if row >= ROWS {
+            warn!("No space left for macro {:?}", macro_.name);
+            continue;
+        }
+        buttons.push(Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62 + (width + 10) * column,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * row,
+                width,
+                height: 100,
+            },
+            label: macro_.name.clone(),
+            label_size: 40.0,
+            action: ButtonAction::Macro(macro_),
+        });
+    }
+
+    Layout::new(buttons)
+}
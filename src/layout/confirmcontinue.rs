@@ -0,0 +1,52 @@
+use super::{ButtonAction, Element, Layout, LayoutId};
+use crate::autosave;
+use libremarkable::framebuffer::common;
+
+pub fn create() -> Layout {
+    let buttons = vec![
+        Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: "Continue?".into(),
+            size: 100.0,
+        },
+        Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10 + 100,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: "Load the game that got autosaved last time.".into(),
+            size: 50.0,
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: (common::DISPLAYWIDTH as u32 - (300 + 50 + 300)) / 2,
+                top: 1400 - 300 - 10 - 10 + 100 + 75 + 50,
+                width: 300,
+                height: 150,
+            },
+            label: "Continue".into(),
+            label_size: 75.0,
+            action: ButtonAction::LoadGame(autosave::SLOT),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: (common::DISPLAYWIDTH as u32 - (300 + 50 + 300)) / 2 + 300 + 50,
+                top: 1400 - 300 - 10 - 10 + 100 + 75 + 50,
+                width: 300,
+                height: 150,
+            },
+            label: "Skip".into(),
+            label_size: 75.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Controls),
+        },
+    ];
+
+    Layout::new(buttons)
+}
@@ -1,7 +1,8 @@
 use super::{ButtonAction, Element, Layout, LayoutId};
+use crate::config::Config;
 use libremarkable::framebuffer::common;
 
-pub fn create() -> Layout {
+pub fn create(config: &Config) -> Layout {
     let buttons = vec![
         Element::Text {
             rect: common::mxcfb_rect {
@@ -10,7 +11,7 @@ pub fn create() -> Layout {
                 width: common::DISPLAYWIDTH as u32,
                 height: 100,
             },
-            text: "Are you sure?",
+            text: "Are you sure?".into(),
             size: 100.0,
         },
         Element::Text {
@@ -20,7 +21,12 @@ pub fn create() -> Layout {
                 width: common::DISPLAYWIDTH as u32,
                 height: 100,
             },
-            text: "Any unsaved progress will get lost!",
+            text: if config.autosave {
+                "The game will be saved in the last slot."
+            } else {
+                "Any unsaved progress will get lost!"
+            }
+            .into(),
             size: 50.0,
         },
         Element::Button {
@@ -30,11 +36,9 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 150,
             },
-            label: "Exit",
+            label: "Exit".into(),
             label_size: 75.0,
-            action: ButtonAction::Function(Box::new(|| {
-                std::process::exit(0);
-            })),
+            action: ButtonAction::Exit,
         },
         Element::Button {
             rect: common::mxcfb_rect {
@@ -43,7 +47,7 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 150,
             },
-            label: "Back",
+            label: "Back".into(),
             label_size: 75.0,
             action: ButtonAction::SwitchLayout(LayoutId::Settings),
         },
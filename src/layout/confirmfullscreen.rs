@@ -10,7 +10,7 @@ pub fn create() -> Layout {
                 width: common::DISPLAYWIDTH as u32,
                 height: 100,
             },
-            text: "Go Fullscreen?",
+            text: "Go Fullscreen?".into(),
             size: 100.0,
         },
         Element::Text {
@@ -20,7 +20,7 @@ pub fn create() -> Layout {
                 width: common::DISPLAYWIDTH as u32,
                 height: 100,
             },
-            text: "You'll need to attach a physical keyboard to play!",
+            text: "The controls will be on both sides of the game.".into(),
             size: 50.0,
         },
         Element::Text {
@@ -30,7 +30,7 @@ pub fn create() -> Layout {
                 width: common::DISPLAYWIDTH as u32,
                 height: 100,
             },
-            text: "To exit fullscreen, just touch anywhere.",
+            text: "To exit fullscreen, use the \"Exit fullscreen\" button.".into(),
             size: 50.0,
         },
         Element::Button {
@@ -40,7 +40,7 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 150,
             },
-            label: "OK",
+            label: "OK".into(),
             label_size: 75.0,
             action: ButtonAction::EnterFullscreen,
         },
@@ -51,7 +51,7 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 150,
             },
-            label: "Back",
+            label: "Back".into(),
             label_size: 75.0,
             action: ButtonAction::SwitchLayout(LayoutId::Settings),
         },
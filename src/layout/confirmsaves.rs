@@ -0,0 +1,84 @@
+use super::{ButtonAction, Element, Layout, LayoutId};
+use crate::saves;
+use libremarkable::framebuffer::common;
+
+/// Changes of the saves that replace or remove slots, so they need confirming
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavesChange {
+    Delete(usize),
+    RestoreLatestBackup,
+}
+
+impl SavesChange {
+    pub fn apply(self) {
+        match self {
+            SavesChange::Delete(slot) => saves::delete(slot),
+            SavesChange::RestoreLatestBackup => saves::restore_latest_backup(),
+        }
+    }
+
+    fn description(self) -> String {
+        match self {
+            SavesChange::Delete(slot) => {
+                format!("Slot {} will be moved into the backups.", slot + 1)
+            }
+            SavesChange::RestoreLatestBackup => {
+                "All slots will be replaced by the latest backup.".into()
+            }
+        }
+    }
+}
+
+/// Without a change (before one was chosen), there's only the way back
+pub fn create(change: Option<SavesChange>) -> Layout {
+    let mut buttons = vec![Element::Text {
+        rect: common::mxcfb_rect {
+            left: 0,
+            top: 1400 - 300 - 10 - 10,
+            width: common::DISPLAYWIDTH as u32,
+            height: 100,
+        },
+        text: "Are you sure?".into(),
+        size: 100.0,
+    }];
+    if let Some(change) = change {
+        buttons.push(Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10 + 100,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: change.description(),
+            size: 50.0,
+        });
+        buttons.push(Element::Button {
+            rect: common::mxcfb_rect {
+                left: (common::DISPLAYWIDTH as u32 - (300 + 50 + 300)) / 2,
+                top: 1400 - 300 - 10 - 10 + 100 + 75 + 50,
+                width: 300,
+                height: 150,
+            },
+            label: match change {
+                SavesChange::Delete(_) => "Delete",
+                SavesChange::RestoreLatestBackup => "Restore",
+            }
+            .into(),
+            label_size: 75.0,
+            action: ButtonAction::ApplySavesChange(change),
+        });
+    }
+    buttons.push(Element::Button {
+        rect: common::mxcfb_rect {
+            left: (common::DISPLAYWIDTH as u32 - (300 + 50 + 300)) / 2 + 300 + 50,
+            top: 1400 - 300 - 10 - 10 + 100 + 75 + 50,
+            width: 300,
+            height: 150,
+        },
+        label: "Back".into(),
+        label_size: 75.0,
+        action: ButtonAction::SwitchLayout(LayoutId::Saves),
+    });
+
+    Layout::new(buttons)
+}
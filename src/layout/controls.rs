@@ -1,4 +1,4 @@
-use super::{ButtonAction, Element, Layout, LayoutId};
+use super::{ButtonAction, Element, Layout, LayoutId, KEY_MAP, KEY_RUN};
 use doomgeneric::input::keys;
 use libremarkable::framebuffer::common;
 
@@ -11,9 +11,9 @@ pub fn create() -> Layout {
                 width: 200,
                 height: 200 + 10 + 200,
             },
-            label: "<",
+            label: "<".into(),
             label_size: 100.0,
-            action: ButtonAction::DoomKey(*keys::KEY_LEFT),
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_LEFT),
         },
         Element::Button {
             rect: common::mxcfb_rect {
@@ -22,9 +22,9 @@ pub fn create() -> Layout {
                 width: 200,
                 height: 200,
             },
-            label: "^",
+            label: "^".into(),
             label_size: 100.0,
-            action: ButtonAction::DoomKey(*keys::KEY_UP),
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_UP),
         },
         Element::Button {
             rect: common::mxcfb_rect {
@@ -33,9 +33,9 @@ pub fn create() -> Layout {
                 width: 200,
                 height: 200,
             },
-            label: "v",
+            label: "v".into(),
             label_size: 100.0,
-            action: ButtonAction::DoomKey(*keys::KEY_DOWN),
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_DOWN),
         },
         Element::Button {
             rect: common::mxcfb_rect {
@@ -44,9 +44,9 @@ pub fn create() -> Layout {
                 width: 200,
                 height: 200 + 10 + 200,
             },
-            label: ">",
+            label: ">".into(),
             label_size: 100.0,
-            action: ButtonAction::DoomKey(*keys::KEY_RIGHT),
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_RIGHT),
         },
         Element::Button {
             rect: common::mxcfb_rect {
@@ -55,7 +55,7 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 200 + 10 + 200,
             },
-            label: "Strafe",
+            label: "Strafe".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(*keys::KEY_STRAFE),
         },
@@ -66,7 +66,7 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 200 + 10 + 200,
             },
-            label: "Fire",
+            label: "Fire".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(*keys::KEY_FIRE),
         },
@@ -77,7 +77,7 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 150,
             },
-            label: "ESC",
+            label: "ESC".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::KEY_ESCAPE),
         },
@@ -88,9 +88,12 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 150,
             },
-            label: "Enter",
+            label: "Enter (2x: Yes)".into(),
             label_size: 25.0,
-            action: ButtonAction::DoomKey(keys::KEY_ENTER),
+            action: ButtonAction::DoubleTapDoomKey {
+                key: keys::KEY_ENTER,
+                double_tap_key: b'y',
+            },
         },
         Element::Button {
             rect: common::mxcfb_rect {
@@ -99,9 +102,12 @@ pub fn create() -> Layout {
                 width: 300,
                 height: 150 + 10 + 150,
             },
-            label: "Use",
+            label: "Use (hold: Map)".into(),
             label_size: 25.0,
-            action: ButtonAction::DoomKey(*keys::KEY_USE),
+            action: ButtonAction::LongPressDoomKey {
+                key: *keys::KEY_USE,
+                long_press_key: KEY_MAP,
+            },
         },
         Element::Button {
             rect: common::mxcfb_rect {
@@ -110,7 +116,7 @@ pub fn create() -> Layout {
                 width: 100,
                 height: 50,
             },
-            label: "Settings",
+            label: "Settings".into(),
             label_size: 25.0,
             action: ButtonAction::SwitchLayout(LayoutId::Settings),
         },
@@ -121,7 +127,7 @@ pub fn create() -> Layout {
                 width: 100,
                 height: 50,
             },
-            label: "Keyboard",
+            label: "Keyboard".into(),
             label_size: 25.0,
             action: ButtonAction::SwitchLayout(LayoutId::Keyboard),
         },
@@ -132,7 +138,7 @@ pub fn create() -> Layout {
                 width: 75,
                 height: 75,
             },
-            label: "2",
+            label: "2".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::from_char('2').unwrap()),
         },
@@ -143,7 +149,7 @@ pub fn create() -> Layout {
                 width: 75,
                 height: 75,
             },
-            label: "3",
+            label: "3".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::from_char('3').unwrap()),
         },
@@ -154,7 +160,7 @@ pub fn create() -> Layout {
                 width: 75,
                 height: 75,
             },
-            label: "4",
+            label: "4".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::from_char('4').unwrap()),
         },
@@ -165,7 +171,7 @@ pub fn create() -> Layout {
                 width: 75,
                 height: 75,
             },
-            label: "5",
+            label: "5".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::from_char('5').unwrap()),
         },
@@ -176,7 +182,7 @@ pub fn create() -> Layout {
                 width: 75,
                 height: 75,
             },
-            label: "6",
+            label: "6".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::from_char('6').unwrap()),
         },
@@ -187,7 +193,7 @@ pub fn create() -> Layout {
                 width: 75,
                 height: 75,
             },
-            label: "7",
+            label: "7".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::from_char('7').unwrap()),
         },
@@ -198,10 +204,32 @@ pub fn create() -> Layout {
                 width: (75 - 4) * 3 + 4,
                 height: 75,
             },
-            label: "Arms",
+            label: "Arms".into(),
             label_size: 25.0,
             action: ButtonAction::DoomKey(keys::from_char('1').unwrap()),
         },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 190 - 10 - 190,
+                top: 1400 - 10 - 200,
+                width: 190,
+                height: 200,
+            },
+            label: "Hold fire".into(),
+            label_size: 25.0,
+            action: ButtonAction::ToggleDoomKey(*keys::KEY_FIRE),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 190,
+                top: 1400 - 10 - 200,
+                width: 190,
+                height: 200,
+            },
+            label: "Run".into(),
+            label_size: 25.0,
+            action: ButtonAction::ToggleDoomKey(KEY_RUN),
+        },
     ];
 
     Layout::new(buttons)
@@ -0,0 +1,83 @@
+use super::{ButtonAction, Element, Layout, LayoutId};
+use crate::evdev_keyboard;
+use libremarkable::framebuffer::common;
+
+const ROWS: usize = 5;
+/// Longer device names get cut off to fit into the buttons
+const MAX_NAME_LENGTH: usize = 32;
+
+pub fn create() -> Layout {
+    let devices = evdev_keyboard::connected_devices();
+
+    let mut buttons = vec![
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 100,
+                top: 1400 - 300 - 10 - 10,
+                width: 100,
+                height: 50,
+            },
+            label: "Back".into(),
+            label_size: 25.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Settings),
+        },
+        Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: format!("Input devices ({})", devices.len()),
+            size: 100.0,
+        },
+    ];
+
+    if devices.is_empty() {
+        buttons.push(Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 + 100 + 10,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: "No keyboard, gamepad or mouse connected.".into(),
+            size: 50.0,
+        });
+    }
+
+    for (row, device) in devices.into_iter().enumerate() {
+        if row >= ROWS {
+            warn!("No space left to list device {:?}", device.name);
+            continue;
+        }
+        let mut name: String = device.name.chars().take(MAX_NAME_LENGTH).collect();
+        if name.len() < device.name.len() {
+            name.push_str("...");
+        }
+        let state = if !device.enabled {
+            "Off"
+        } else if device.grabbed {
+            "On (grabbed)"
+        } else {
+            "On"
+        };
+
+        buttons.push(Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * row as u32,
+                width: common::DISPLAYWIDTH as u32 - 62 * 2,
+                height: 100,
+            },
+            label: format!("{}: {} - {}", device.kind.name(), name, state),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSetting(Box::new(move |config| {
+                let enabled = config.is_device_enabled(&device.name, &device.path);
+                config.set_device_enabled(&device.name, &device.path, !enabled);
+            })),
+        });
+    }
+
+    Layout::new(buttons)
+}
@@ -0,0 +1,233 @@
+//! Controls drawn into the empty bands left and right of the game while in fullscreen.
+//! All rects are in landscape coordinates (1872x1404) and get rotated like the game.
+
+use super::{ButtonAction, Element, Layout, KEY_MAP, KEY_RUN};
+use doomgeneric::input::keys;
+use libremarkable::framebuffer::common;
+
+pub fn create() -> Layout {
+    // Landscape game is 1280 px wide, leaving (1872 - 1280) / 2 = 296 px on each side
+    let right_band = 1872 - 296;
+
+    let buttons = vec![
+        // Left band
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8,
+                top: 62,
+                width: 280,
+                height: 100,
+            },
+            label: "Exit fullscreen".into(),
+            label_size: 25.0,
+            action: ButtonAction::ExitFullscreen,
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8,
+                top: 62 + 100 + 10,
+                width: 280,
+                height: 100,
+            },
+            label: "ESC".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::KEY_ESCAPE),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8,
+                top: 62 + (100 + 10) * 2,
+                width: 280,
+                height: 100,
+            },
+            label: "Enter (2x: Yes)".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoubleTapDoomKey {
+                key: keys::KEY_ENTER,
+                double_tap_key: b'y',
+            },
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8 + 90 + 8,
+                top: 1404 - 62 - 90 * 3 - 8 * 2,
+                width: 90,
+                height: 90,
+            },
+            label: "^".into(),
+            label_size: 75.0,
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_UP),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8,
+                top: 1404 - 62 - 90 * 2 - 8,
+                width: 90,
+                height: 90,
+            },
+            label: "<".into(),
+            label_size: 75.0,
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_LEFT),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8 + (90 + 8) * 2,
+                top: 1404 - 62 - 90 * 2 - 8,
+                width: 90,
+                height: 90,
+            },
+            label: ">".into(),
+            label_size: 75.0,
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_RIGHT),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8 + 90 + 8,
+                top: 1404 - 62 - 90,
+                width: 90,
+                height: 90,
+            },
+            label: "v".into(),
+            label_size: 75.0,
+            action: ButtonAction::RepeatDoomKey(*keys::KEY_DOWN),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8,
+                top: 1404 - 62 - 90 * 3 - 8 * 2 - 10 - 150 - 10 - 150,
+                width: 280,
+                height: 150,
+            },
+            label: "Hold fire".into(),
+            label_size: 25.0,
+            action: ButtonAction::ToggleDoomKey(*keys::KEY_FIRE),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 8,
+                top: 1404 - 62 - 90 * 3 - 8 * 2 - 10 - 150,
+                width: 280,
+                height: 150,
+            },
+            label: "Run".into(),
+            label_size: 25.0,
+            action: ButtonAction::ToggleDoomKey(KEY_RUN),
+        },
+        // Right band
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8 + (90 + 5) * 0,
+                top: 62,
+                width: 90,
+                height: 90,
+            },
+            label: "2".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::from_char('2').unwrap()),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8 + (90 + 5) * 1,
+                top: 62,
+                width: 90,
+                height: 90,
+            },
+            label: "3".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::from_char('3').unwrap()),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8 + (90 + 5) * 2,
+                top: 62,
+                width: 90,
+                height: 90,
+            },
+            label: "4".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::from_char('4').unwrap()),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8 + (90 + 5) * 0,
+                top: 62 + 90 + 5,
+                width: 90,
+                height: 90,
+            },
+            label: "5".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::from_char('5').unwrap()),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8 + (90 + 5) * 1,
+                top: 62 + 90 + 5,
+                width: 90,
+                height: 90,
+            },
+            label: "6".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::from_char('6').unwrap()),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8 + (90 + 5) * 2,
+                top: 62 + 90 + 5,
+                width: 90,
+                height: 90,
+            },
+            label: "7".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::from_char('7').unwrap()),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8,
+                top: 62 + (90 + 5) * 2,
+                width: 90 * 3 + 5 * 2,
+                height: 90,
+            },
+            label: "Arms".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(keys::from_char('1').unwrap()),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8,
+                top: 1404 - 62 - 400 - 10 - 200 - 10 - 150,
+                width: 280,
+                height: 150,
+            },
+            label: "Strafe".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(*keys::KEY_STRAFE),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8,
+                top: 1404 - 62 - 400 - 10 - 200,
+                width: 280,
+                height: 200,
+            },
+            label: "Use (hold: Map)".into(),
+            label_size: 25.0,
+            action: ButtonAction::LongPressDoomKey {
+                key: *keys::KEY_USE,
+                long_press_key: KEY_MAP,
+            },
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: right_band + 8,
+                top: 1404 - 62 - 400,
+                width: 280,
+                height: 400,
+            },
+            label: "Fire".into(),
+            label_size: 25.0,
+            action: ButtonAction::DoomKey(*keys::KEY_FIRE),
+        },
+    ];
+
+    Layout::new(buttons)
+}
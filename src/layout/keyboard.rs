@@ -1,104 +1,253 @@
 use super::{ButtonAction, Element, Layout, LayoutId};
 use libremarkable::framebuffer::common;
 
+/// Doom keys of the modifiers (RCTRL and RALT, see doomkeys.h)
+const KEY_CTRL: u8 = 0x80 + 0x1d;
+const KEY_ALT: u8 = 0x80 + 0x38;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyboardVariant {
+    #[default]
+    Qwerty,
+    Qwertz,
+    Azerty,
+}
+
+impl KeyboardVariant {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyboardVariant::Qwerty => "QWERTY",
+            KeyboardVariant::Qwertz => "QWERTZ",
+            KeyboardVariant::Azerty => "AZERTY",
+        }
+    }
+
+    /// Characters of the keys between the special keys of the first four rows.
+    /// Two per key: normal and with shift. A space means that the key types nothing,
+    /// since Doom only knows ASCII (no umlauts or accents).
+    fn character_rows(self) -> [&'static str; 4] {
+        match self {
+            KeyboardVariant::Qwerty => [
+                "`~1!2@3#4$5%6^7&8*9(0)-_=+",
+                "qQwWeErRtTyYuUiIoOpP[{]}\\|",
+                "aAsSdDfFgGhHjJkKlL;:'\"",
+                "zZxXcCvVbBnNmM,<.>/?",
+            ],
+            KeyboardVariant::Qwertz => [
+                "^ 1!2\"3 4$5%6&7/8(9)0= ? `",
+                "qQwWeErRtTzZuUiIoOpP  +*#'",
+                "aAsSdDfFgGhHjJkKlL    ",
+                "yYxXcCvVbBnNmM,;.:-_",
+            ],
+            KeyboardVariant::Azerty => [
+                "  &1 2\"3'4(5-6 7_8 9 0) =+",
+                "aAzZeErRtTyYuUiIoOpP^ $ * ",
+                "qQsSdDfFgGhHjJkKlLmM %",
+                "wWxXcCvVbBnN,?;.:/! ",
+            ],
+        }
+    }
+}
+
+/// Same positions as [`KeyboardVariant::character_rows`], but one character per key
+const SYMBOL_ROWS: [&str; 4] = [
+    "`1234567890-=",
+    "!@#$%^&*()[]\\",
+    "~_+{}|:;\"'/",
+    "<>,.?=-+*_",
+];
+
+/// Modifiers and page of the keyboard. Kept by the layout manager, since the keyboard
+/// layout gets recreated whenever this changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyboardState {
+    /// Sticky modifiers, released after the next typed key
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub caps_lock: bool,
+    pub symbols: bool,
+}
+
+impl KeyboardState {
+    pub fn release_sticky(&mut self) {
+        self.shift = false;
+        self.ctrl = false;
+        self.alt = false;
+    }
+
+    fn toggle_shift(&mut self) {
+        self.shift = !self.shift;
+    }
+
+    fn toggle_ctrl(&mut self) {
+        self.ctrl = !self.ctrl;
+    }
+
+    fn toggle_alt(&mut self) {
+        self.alt = !self.alt;
+    }
+
+    fn toggle_caps_lock(&mut self) {
+        self.caps_lock = !self.caps_lock;
+    }
+
+    fn toggle_symbols(&mut self) {
+        self.symbols = !self.symbols;
+    }
+
+    /// Doom keys that are held down together with a typed key
+    fn modifier_keys(&self) -> Vec<u8> {
+        let mut keys = vec![];
+        if self.ctrl {
+            keys.push(KEY_CTRL);
+        }
+        if self.alt {
+            keys.push(KEY_ALT);
+        }
+        keys
+    }
+}
+
+enum KeyKind {
+    /// Types the character (nothing if '\0')
+    Char(char),
+    /// Types a key that is not a character (e.g. Enter)
+    Special(u8),
+    /// Like Special, but repeats while being held
+    Repeating(u8),
+    /// Changes the modifiers or page. Active ones are shown as held.
+    Toggle {
+        change: fn(&mut KeyboardState),
+        active: bool,
+    },
+}
+
 struct KeyDefinition {
     weighted_width: f32,
-    title: &'static str,
-    primary_letter: char,
+    title: String,
+    kind: KeyKind,
 }
 
 impl KeyDefinition {
-    fn new(weighted_width: f32, title: &'static str, primary_letter: char) -> Self {
+    fn new(weighted_width: f32, title: &str, kind: KeyKind) -> Self {
+        Self {
+            weighted_width,
+            title: title.into(),
+            kind,
+        }
+    }
+
+    fn character(weighted_width: f32, c: char) -> Self {
+        let title = if c == '\0' {
+            String::new()
+        } else {
+            c.to_string()
+        };
         Self {
             weighted_width,
             title,
-            primary_letter,
+            kind: KeyKind::Char(c),
         }
     }
 }
 
-fn create_keyboard_definitions() -> Vec<Vec<KeyDefinition>> {
-    vec![
-        // Row 1
-        vec![
-            KeyDefinition::new(61.0, "^", '^'),
-            KeyDefinition::new(61.0, "1", '1'),
-            KeyDefinition::new(61.0, "2", '2'),
-            KeyDefinition::new(61.0, "3", '3'),
-            KeyDefinition::new(61.0, "4", '4'),
-            KeyDefinition::new(61.0, "5", '5'),
-            KeyDefinition::new(61.0, "6", '6'),
-            KeyDefinition::new(61.0, "7", '7'),
-            KeyDefinition::new(61.0, "8", '8'),
-            KeyDefinition::new(61.0, "9", '9'),
-            KeyDefinition::new(61.0, "0", '0'),
-            KeyDefinition::new(61.0, "-", '-'),
-            KeyDefinition::new(61.0, "+", '+'),
-            KeyDefinition::new(120.0, "Backspace", '\x7f'),
-        ],
-        // Row 2
-        vec![
-            KeyDefinition::new(90.0, "Tab", '\x09'),
-            KeyDefinition::new(61.0, "q", 'q'),
-            KeyDefinition::new(61.0, "w", 'w'),
-            KeyDefinition::new(61.0, "e", 'e'),
-            KeyDefinition::new(61.0, "r", 'r'),
-            KeyDefinition::new(61.0, "t", 't'),
-            KeyDefinition::new(61.0, "y", 'y'),
-            KeyDefinition::new(61.0, "u", 'u'),
-            KeyDefinition::new(61.0, "i", 'i'),
-            KeyDefinition::new(61.0, "o", 'o'),
-            KeyDefinition::new(61.0, "p", 'p'),
-            KeyDefinition::new(61.0, "" /*"{"*/, '\0'),
-            KeyDefinition::new(61.0, "" /*"}"*/, '\0'),
-            KeyDefinition::new(91.0, "" /*"|"*/, '\0'),
-        ],
-        // Row 3
-        vec![
-            KeyDefinition::new(106.0, "Caps Lock", 0xba as char),
-            KeyDefinition::new(61.0, "a", 'a'),
-            KeyDefinition::new(61.0, "s", 's'),
-            KeyDefinition::new(61.0, "d", 'd'),
-            KeyDefinition::new(61.0, "f", 'f'),
-            KeyDefinition::new(61.0, "g", 'g'),
-            KeyDefinition::new(61.0, "h", 'h'),
-            KeyDefinition::new(61.0, "j", 'j'),
-            KeyDefinition::new(61.0, "k", 'k'),
-            KeyDefinition::new(61.0, "l", 'l'),
-            KeyDefinition::new(61.0, ":", ':'),
-            KeyDefinition::new(61.0, "\"", '\"'),
-            KeyDefinition::new(136.0, "Enter", '\x0d'),
-        ],
-        // Row 4
-        vec![
-            KeyDefinition::new(136.0, "Shift", '\x0e'), // Shift in??
-            KeyDefinition::new(61.0, "z", 'z'),
-            KeyDefinition::new(61.0, "x", 'x'),
-            KeyDefinition::new(61.0, "c", 'c'),
-            KeyDefinition::new(61.0, "v", 'v'),
-            KeyDefinition::new(61.0, "b", 'b'),
-            KeyDefinition::new(61.0, "n", 'n'),
-            KeyDefinition::new(61.0, "m", 'm'),
-            KeyDefinition::new(61.0, "<", '<'),
-            KeyDefinition::new(61.0, ">", '>'),
-            KeyDefinition::new(61.0, "?", '?'),
-            KeyDefinition::new(167.0, "Shift", 0xb6 as char), // Shift in??
-        ],
-        // Row 5
-        vec![
-            KeyDefinition::new(92.0, "Ctrl", 0x9d as char),
-            KeyDefinition::new(61.0, /*"Super"*/ "", '\0'),
-            KeyDefinition::new(92.0, "Alt", 0xb8 as char),
-            KeyDefinition::new(362.0, "Space", ' '),
-            KeyDefinition::new(92.0, "Alt", 0xb8 as char),
-            KeyDefinition::new(61.0, "" /*"Super"*/, '\0'),
-            KeyDefinition::new(61.0, "" /*"Menu"*/, '\0'),
-            KeyDefinition::new(92.0, "Ctrl", 0x9d as char),
-        ],
-    ]
+/// Character keys of a row as they are currently typed
+fn row_characters(row: usize, variant: KeyboardVariant, state: &KeyboardState) -> Vec<char> {
+    if state.symbols {
+        return SYMBOL_ROWS[row].chars().collect();
+    }
+    let chars: Vec<char> = variant.character_rows()[row].chars().collect();
+    chars
+        .chunks(2)
+        .map(|pair| {
+            let (normal, shifted) = (pair[0], pair[1]);
+            let shift = if normal.is_ascii_alphabetic() {
+                state.shift != state.caps_lock
+            } else {
+                state.shift
+            };
+            match if shift { shifted } else { normal } {
+                ' ' => '\0',
+                c => c,
+            }
+        })
+        .collect()
+}
+
+fn create_keyboard_definitions(
+    variant: KeyboardVariant,
+    state: &KeyboardState,
+) -> Vec<Vec<KeyDefinition>> {
+    let characters = |row: usize, widths: &[f32]| -> Vec<KeyDefinition> {
+        let chars = row_characters(row, variant, state);
+        assert_eq!(chars.len(), widths.len());
+        chars
+            .into_iter()
+            .zip(widths)
+            .map(|(c, width)| KeyDefinition::character(*width, c))
+            .collect()
+    };
+    let shift = || KeyKind::Toggle {
+        change: KeyboardState::toggle_shift,
+        active: state.shift,
+    };
+    let ctrl = || KeyKind::Toggle {
+        change: KeyboardState::toggle_ctrl,
+        active: state.ctrl,
+    };
+    let alt = || KeyKind::Toggle {
+        change: KeyboardState::toggle_alt,
+        active: state.alt,
+    };
+    let symbols = || KeyKind::Toggle {
+        change: KeyboardState::toggle_symbols,
+        active: false,
+    };
+    let symbols_title = if state.symbols { "abc" } else { "123" };
+
+    let mut row1 = characters(0, &[61.0; 13]);
+    row1.push(KeyDefinition::new(
+        120.0,
+        "Backspace",
+        KeyKind::Repeating(0x7f),
+    ));
+
+    let mut row2 = vec![KeyDefinition::new(90.0, "Tab", KeyKind::Special(0x09))];
+    let mut row2_widths = vec![61.0; 12];
+    row2_widths.push(91.0);
+    row2.extend(characters(1, &row2_widths));
+
+    let mut row3 = vec![KeyDefinition::new(
+        106.0,
+        "Caps Lock",
+        KeyKind::Toggle {
+            change: KeyboardState::toggle_caps_lock,
+            active: state.caps_lock,
+        },
+    )];
+    row3.extend(characters(2, &[61.0; 11]));
+    row3.push(KeyDefinition::new(136.0, "Enter", KeyKind::Special(0x0d)));
+
+    let mut row4 = vec![KeyDefinition::new(136.0, "Shift", shift())];
+    row4.extend(characters(3, &[61.0; 10]));
+    row4.push(KeyDefinition::new(167.0, "Shift", shift()));
+
+    let row5 = vec![
+        KeyDefinition::new(92.0, "Ctrl", ctrl()),
+        KeyDefinition::new(61.0, symbols_title, symbols()),
+        KeyDefinition::new(92.0, "Alt", alt()),
+        KeyDefinition::new(362.0, "Space", KeyKind::Char(' ')),
+        KeyDefinition::new(92.0, "Alt", alt()),
+        KeyDefinition::new(61.0, symbols_title, symbols()),
+        KeyDefinition::new(61.0, "Esc", KeyKind::Special(27)),
+        KeyDefinition::new(92.0, "Ctrl", ctrl()),
+    ];
+
+    vec![row1, row2, row3, row4, row5]
 }
 
-pub fn create() -> Layout {
+pub fn create(variant: KeyboardVariant, state: &KeyboardState) -> Layout {
     let mut buttons = vec![Element::Button {
         rect: common::mxcfb_rect {
             left: 1404 - 62 - 100,
@@ -106,12 +255,12 @@ pub fn create() -> Layout {
             width: 100,
             height: 50,
         },
-        label: "Back",
+        label: "Back".into(),
         label_size: 25.0,
         action: ButtonAction::SwitchLayout(LayoutId::Controls),
     }];
 
-    let keys = create_keyboard_definitions();
+    let keys = create_keyboard_definitions(variant, state);
     let weighted_height = 61f32;
     //let height_weight_sum = weighted_height * keys.len() as f32;
     let width_weight_sum = keys[0].iter().map(|k| k.weighted_width).sum::<f32>();
@@ -137,7 +286,20 @@ pub fn create() -> Layout {
                 },
                 label: key.title,
                 label_size: 25.0,
-                action: ButtonAction::DoomKey(key.primary_letter as u8),
+                action: match key.kind {
+                    KeyKind::Char(c) => ButtonAction::TypeKey {
+                        key: c as u8,
+                        modifiers: state.modifier_keys(),
+                    },
+                    KeyKind::Special(key) => ButtonAction::TypeKey {
+                        key,
+                        modifiers: state.modifier_keys(),
+                    },
+                    KeyKind::Repeating(key) => ButtonAction::RepeatDoomKey(key),
+                    KeyKind::Toggle { change, active } => {
+                        ButtonAction::ChangeKeyboard { change, active }
+                    }
+                },
             });
             x += key.weighted_width * width_factor;
         }
@@ -0,0 +1,57 @@
+use super::{ButtonAction, Element, Layout, LayoutId};
+use crate::config::Config;
+use libremarkable::framebuffer::common;
+
+pub fn create(config: &Config) -> Layout {
+    let buttons = vec![
+        Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: "Battery low!".into(),
+            size: 100.0,
+        },
+        Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10 + 100,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: if config.autosave {
+                "Paused. Saving in the last slot, if in a level."
+            } else {
+                "Paused. Save the game before the battery runs out!"
+            }
+            .into(),
+            size: 50.0,
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: (common::DISPLAYWIDTH as u32 - (300 + 50 + 300)) / 2,
+                top: 1400 - 300 - 10 - 10 + 100 + 75 + 50,
+                width: 300,
+                height: 150,
+            },
+            label: "Continue".into(),
+            label_size: 75.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Controls),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: (common::DISPLAYWIDTH as u32 - (300 + 50 + 300)) / 2 + 300 + 50,
+                top: 1400 - 300 - 10 - 10 + 100 + 75 + 50,
+                width: 300,
+                height: 150,
+            },
+            label: "Exit".into(),
+            label_size: 75.0,
+            action: ButtonAction::Exit,
+        },
+    ];
+
+    Layout::new(buttons)
+}
@@ -1,27 +1,81 @@
 //! Mostly the ui-like stuff below the game
 
-use doomgeneric::input::KeyData;
+use doomgeneric::input::{keys, KeyData};
 use libremarkable::cgmath::{Point2, Vector2};
 use libremarkable::framebuffer::core::Framebuffer;
 use libremarkable::framebuffer::{common, PartialRefreshMode};
 use libremarkable::framebuffer::{FramebufferDraw, FramebufferIO, FramebufferRefresh};
 use libremarkable::input::{Finger, InputEvent, MultitouchEvent};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 
+use crate::autosave;
+use crate::config::{Config, CONFIG};
+use crate::macros::Macro;
+use crate::pause;
+use crate::rotation::{self, Rotation};
+
+mod cheats;
+mod confirmcontinue;
 mod confirmexit;
 mod confirmfullscreen;
+mod confirmsaves;
 mod controls;
+mod devices;
+mod fullscreen;
 mod keyboard;
+mod lowbattery;
+mod saves;
 mod settings;
 
+pub use confirmsaves::SavesChange;
+pub use keyboard::{KeyboardState, KeyboardVariant};
+
+/// Width of the border that shows a button being held down
+const PRESSED_BORDER_WIDTH: u32 = 10;
+/// Distance of the focus ring from the edge of a button and its width
+const FOCUS_RING_INSET: u32 = 8;
+const FOCUS_RING_WIDTH: u32 = 3;
+/// How long a tapped key stays pressed, so the game notices it (one tic is ~29 ms)
+const TAP_RELEASE_DELAY: Duration = Duration::from_millis(100);
+/// Doom key for running (RSHIFT, see doomkeys.h)
+pub(crate) const KEY_RUN: u8 = 0x80 + 0x36;
+/// Doom key for toggling the automap (TAB)
+pub(crate) const KEY_MAP: u8 = 9;
+/// Buttons at most this far apart keep their order when mirroring the layout
+const MIRROR_GROUP_GAP: u32 = 20;
+/// How often time based button behaviours (long press, repeat) should be checked
+pub const TICK_INTERVAL: Duration = Duration::from_millis(20);
+
 pub enum InputOutcome {
     KeyData(KeyData),
+    PlayMacro(Macro),
     SwitchLayout(LayoutId),
     EnterFullscreen,
+    ExitFullscreen,
+    /// The config was changed and saved. Anything depending on it should be redrawn.
+    SettingsChanged,
+    /// Modifiers or page of the on-screen keyboard changed
+    ChangeKeyboard(fn(&mut KeyboardState)),
+    /// Shut down doomarkable (after autosaving if enabled)
+    Exit,
+    /// Savegame files were modified and should be listed again
+    SavesChanged,
+    /// Ask before deleting or replacing savegames
+    ConfirmSavesChange(SavesChange),
+    /// Save into the autosave slot
+    Autosave,
 }
 
 pub struct LayoutManager {
     layouts: fxhash::FxHashMap<LayoutId, Layout>,
     current_layout_id: LayoutId,
+    keyboard_state: KeyboardState,
+    /// Shown by the ConfirmSaves layout
+    saves_change: Option<SavesChange>,
+    /// For keys that need to be sent when switching layouts
+    keydata_tx: Sender<KeyData>,
+    suspended: bool,
 }
 
 fn combined_rect(mut rect_iter: impl Iterator<Item = common::mxcfb_rect>) -> common::mxcfb_rect {
@@ -50,27 +104,181 @@ fn combined_rect(mut rect_iter: impl Iterator<Item = common::mxcfb_rect>) -> com
     }
 }
 
+/// Larger by the margin on all sides
+fn grown(rect: &common::mxcfb_rect, margin: u32) -> common::mxcfb_rect {
+    common::mxcfb_rect {
+        left: rect.left.saturating_sub(margin),
+        top: rect.top.saturating_sub(margin),
+        width: rect.width + margin * 2,
+        height: rect.height + margin * 2,
+    }
+}
+
+/// Whether two rects overlap or share an edge
+fn rects_touch(a: &common::mxcfb_rect, b: &common::mxcfb_rect) -> bool {
+    a.left <= b.left + b.width
+        && b.left <= a.left + a.width
+        && a.top <= b.top + b.height
+        && b.top <= a.top + a.height
+}
+
 impl LayoutManager {
-    pub fn new(fb: &mut Framebuffer) -> Self {
-        let mut layouts: fxhash::FxHashMap<LayoutId, Layout> = Default::default();
-
-        // Create and add layouts
-        layouts.insert(LayoutId::Controls, controls::create());
-        layouts.insert(LayoutId::Settings, settings::create());
-        layouts.insert(LayoutId::ConfirmExit, confirmexit::create());
-        layouts.insert(LayoutId::Keyboard, keyboard::create());
-        layouts.insert(LayoutId::ConfirmFullscreen, confirmfullscreen::create());
-
-        let instance = Self {
-            layouts,
-            current_layout_id: Default::default(),
+    pub fn new(fb: &mut Framebuffer, keydata_tx: Sender<KeyData>) -> Self {
+        // Without autosaving, the slot is a normal one of the player
+        let autosave_enabled = CONFIG.lock().unwrap().autosave;
+        let mut instance = Self {
+            layouts: Self::create_layouts(&CONFIG.lock().unwrap(), &Default::default(), None),
+            // Offer to load the autosave on launch
+            current_layout_id: if autosave_enabled && autosave::exists() {
+                LayoutId::ConfirmContinue
+            } else {
+                LayoutId::Controls
+            },
+            keyboard_state: Default::default(),
+            saves_change: None,
+            keydata_tx,
+            suspended: false,
         };
-        instance.current_layout().render(fb);
+        instance.current_layout_mut().render(fb);
         instance.refresh(&instance.current_layout().get_area(), fb);
 
         instance
     }
 
+    fn create_layouts(
+        config: &Config,
+        keyboard_state: &KeyboardState,
+        saves_change: Option<SavesChange>,
+    ) -> fxhash::FxHashMap<LayoutId, Layout> {
+        LayoutId::ALL
+            .iter()
+            .map(|id| {
+                let layout = Self::create_layout(*id, config, keyboard_state, saves_change);
+                (*id, layout)
+            })
+            .collect()
+    }
+
+    fn create_layout(
+        id: LayoutId,
+        config: &Config,
+        keyboard_state: &KeyboardState,
+        saves_change: Option<SavesChange>,
+    ) -> Layout {
+        let portrait = config.portrait_rotation;
+        let mut layout = match id {
+            LayoutId::Controls => controls::create()
+                .with_rotation(portrait)
+                .mirrored(config.left_handed),
+            LayoutId::Settings => settings::create(config).with_rotation(portrait),
+            LayoutId::Cheats => cheats::create(config).with_rotation(portrait),
+            LayoutId::Devices => devices::create().with_rotation(portrait),
+            LayoutId::ConfirmExit => confirmexit::create(config).with_rotation(portrait),
+            LayoutId::ConfirmContinue => confirmcontinue::create().with_rotation(portrait),
+            LayoutId::ConfirmSaves => confirmsaves::create(saves_change).with_rotation(portrait),
+            LayoutId::Saves => saves::create().with_rotation(portrait),
+            LayoutId::LowBattery => lowbattery::create(config).with_rotation(portrait),
+            LayoutId::Keyboard => {
+                keyboard::create(config.keyboard_variant, keyboard_state).with_rotation(portrait)
+            }
+            LayoutId::ConfirmFullscreen => confirmfullscreen::create().with_rotation(portrait),
+            LayoutId::Fullscreen => fullscreen::create()
+                .with_rotation(config.fullscreen_rotation)
+                .mirrored(config.left_handed)
+                .hidden(config.fullscreen_scale.covers_controls()),
+        };
+        layout.timing = ButtonTiming::from_config(config);
+        layout
+    }
+
+    /// Recreate all layouts from the current config and render the current one again.
+    /// The area of the current layout is expected to be cleared already.
+    pub fn reload(&mut self, fb: &mut Framebuffer) {
+        let mut layouts = Self::create_layouts(
+            &CONFIG.lock().unwrap(),
+            &self.keyboard_state,
+            self.saves_change,
+        );
+        for (id, layout) in layouts.iter_mut() {
+            layout.carry_over_state(self.layouts.get_mut(id).unwrap());
+        }
+        self.layouts = layouts;
+        self.current_layout_mut().render(fb);
+        self.refresh(&self.current_layout().get_area(), fb);
+    }
+
+    /// Recreate a single layout. If it is the current one, it gets redrawn.
+    fn recreate_layout(&mut self, id: LayoutId, fb: &mut Framebuffer) {
+        let mut layout = Self::create_layout(
+            id,
+            &CONFIG.lock().unwrap(),
+            &self.keyboard_state,
+            self.saves_change,
+        );
+        layout.carry_over_state(self.layouts.get_mut(&id).unwrap());
+        if self.current_layout_id != id {
+            self.layouts.insert(id, layout);
+            return;
+        }
+
+        self.current_layout().clear(fb);
+        let old_area = self.current_layout().get_area();
+        *self.current_layout_mut() = layout;
+        self.current_layout_mut().render(fb);
+        let new_area = self.current_layout().get_area();
+        self.refresh(&combined_rect([old_area, new_area].iter().map(|r| *r)), fb);
+    }
+
+    /// Recreate the device list after devices got connected or disconnected
+    pub fn reload_devices(&mut self, fb: &mut Framebuffer) {
+        self.recreate_layout(LayoutId::Devices, fb);
+    }
+
+    /// List the savegames again after they were changed
+    pub fn reload_saves(&mut self, fb: &mut Framebuffer) {
+        self.recreate_layout(LayoutId::Saves, fb);
+    }
+
+    /// Ask before deleting or replacing savegames
+    pub fn confirm_saves_change(&mut self, change: SavesChange, fb: &mut Framebuffer) {
+        self.saves_change = Some(change);
+        self.recreate_layout(LayoutId::ConfirmSaves, fb);
+        self.switch_layout(LayoutId::ConfirmSaves, fb);
+    }
+
+    /// Change the modifiers or page of the on-screen keyboard and redraw its keycaps
+    pub fn change_keyboard(&mut self, change: fn(&mut KeyboardState), fb: &mut Framebuffer) {
+        let mut keyboard_state = self.keyboard_state;
+        change(&mut keyboard_state);
+        if keyboard_state != self.keyboard_state {
+            self.keyboard_state = keyboard_state;
+            self.recreate_layout(LayoutId::Keyboard, fb);
+        }
+    }
+
+    /// Keys from keyboards go to the game while a game layout is shown. Other layouts
+    /// can be navigated with them. The settings hotkey works everywhere.
+    pub fn handle_key(&mut self, keydata: KeyData) -> Vec<InputOutcome> {
+        if Some(keydata.key) == CONFIG.lock().unwrap().settings_hotkey {
+            if !keydata.pressed {
+                return vec![];
+            }
+            return match self.current_layout_id {
+                LayoutId::Settings => vec![InputOutcome::SwitchLayout(LayoutId::Controls)],
+                LayoutId::Fullscreen => vec![
+                    InputOutcome::ExitFullscreen,
+                    InputOutcome::SwitchLayout(LayoutId::Settings),
+                ],
+                _ => vec![InputOutcome::SwitchLayout(LayoutId::Settings)],
+            };
+        }
+
+        if self.current_layout_id.is_game_layout() {
+            return vec![InputOutcome::KeyData(keydata)];
+        }
+        self.current_layout_mut().handle_key(keydata)
+    }
+
     pub fn current_layout(&self) -> &Layout {
         self.layouts.get(&self.current_layout_id).unwrap()
     }
@@ -80,18 +288,58 @@ impl LayoutManager {
     }
 
     pub fn switch_layout(&mut self, new_layout: LayoutId, fb: &mut Framebuffer) {
+        // Keys can still be held, e.g. when switching with the settings hotkey
+        self.release_all_keys();
+
         self.current_layout().clear(fb);
         let old_area = self.current_layout().get_area();
 
+        // The game might have saved since the list was created
+        if new_layout == LayoutId::Saves {
+            self.recreate_layout(LayoutId::Saves, fb);
+        }
         self.current_layout_id = new_layout;
+        self.update_pause();
 
         self.current_layout().clear(fb);
-        self.current_layout().render(fb);
+        self.current_layout_mut().render(fb);
         let new_ara = self.current_layout().get_area();
         self.refresh(&combined_rect([old_area, new_ara].iter().map(|r| *r)), fb);
         self.current_layout_id = new_layout;
     }
 
+    /// Release everything and pause the game before the device sleeps or gets stopped
+    pub fn suspend(&mut self) {
+        self.release_all_keys();
+        self.suspended = true;
+        self.update_pause();
+    }
+
+    /// Draw the current layout again (the screen is expected to be cleared already)
+    /// and continue the game
+    pub fn resume(&mut self, fb: &mut Framebuffer) {
+        self.current_layout_mut().render(fb);
+        self.suspended = false;
+        self.update_pause();
+    }
+
+    fn release_all_keys(&mut self) {
+        for outcome in self.current_layout_mut().release_all() {
+            if let InputOutcome::KeyData(keydata) = outcome {
+                self.keydata_tx.send(keydata).ok();
+            }
+        }
+    }
+
+    /// Pause the game while suspended or a menu is shown and resume it otherwise
+    fn update_pause(&self) {
+        pause::set(
+            self.suspended
+                || self.current_layout_id.always_pauses_game()
+                || (self.current_layout_id.pauses_game() && CONFIG.lock().unwrap().auto_pause),
+        );
+    }
+
     fn refresh(&self, area: &common::mxcfb_rect, fb: &mut Framebuffer) {
         fb.partial_refresh(
             area,
@@ -112,6 +360,48 @@ pub enum LayoutId {
     ConfirmExit,
     Keyboard,
     ConfirmFullscreen,
+    Fullscreen,
+    Cheats,
+    Devices,
+    ConfirmContinue,
+    Saves,
+    ConfirmSaves,
+    LowBattery,
+}
+
+impl LayoutId {
+    const ALL: [LayoutId; 12] = [
+        LayoutId::Controls,
+        LayoutId::Settings,
+        LayoutId::ConfirmExit,
+        LayoutId::Keyboard,
+        LayoutId::ConfirmFullscreen,
+        LayoutId::Fullscreen,
+        LayoutId::Cheats,
+        LayoutId::Devices,
+        LayoutId::ConfirmContinue,
+        LayoutId::Saves,
+        LayoutId::ConfirmSaves,
+        LayoutId::LowBattery,
+    ];
+
+    /// Layouts that control the game. Keys from keyboards go straight to the game there.
+    fn is_game_layout(self) -> bool {
+        matches!(
+            self,
+            LayoutId::Controls | LayoutId::Keyboard | LayoutId::Fullscreen
+        )
+    }
+
+    /// Layouts that hide the game controls, so the game should not keep running
+    fn pauses_game(self) -> bool {
+        !matches!(self, LayoutId::Controls | LayoutId::Fullscreen)
+    }
+
+    /// Layouts that pause the game even without auto pause
+    fn always_pauses_game(self) -> bool {
+        self == LayoutId::LowBattery
+    }
 }
 
 impl Default for LayoutId {
@@ -120,28 +410,173 @@ impl Default for LayoutId {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+struct ButtonTiming {
+    long_press: Duration,
+    double_tap: Duration,
+    repeat_delay: Duration,
+    repeat_interval: Duration,
+}
+
+impl ButtonTiming {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            long_press: Duration::from_millis(config.long_press_ms),
+            double_tap: Duration::from_millis(config.double_tap_ms),
+            repeat_delay: Duration::from_millis(config.repeat_delay_ms),
+            repeat_interval: Duration::from_millis(config.repeat_interval_ms),
+        }
+    }
+}
+
+/// State of a button that is currently held down
+struct HeldButton {
+    since: Instant,
+    /// Key that was sent as pressed and needs to be released again
+    key_down: Option<u8>,
+    last_repeat: Option<Instant>,
+}
+
 pub struct Layout {
     elements: Vec<Element>,
+    /// Element rects are in the coordinates of this rotation
+    rotation: Rotation,
+    timing: ButtonTiming,
+    /// Buttons still work, but don't get drawn (e.g. when the game covers them)
+    hidden: bool,
 
     // Input tracking
     fingers: fxhash::FxHashMap<i32, Finger>,
     pressed_indices: fxhash::FxHashSet<usize>,
+    /// Pressed buttons as currently shown on screen
+    displayed_pressed_indices: fxhash::FxHashSet<usize>,
+    held_buttons: fxhash::FxHashMap<usize, HeldButton>,
+    latched_indices: fxhash::FxHashSet<usize>,
+    last_released: fxhash::FxHashMap<usize, Instant>,
+    pending_key_releases: Vec<(Instant, u8)>,
+
+    // Keyboard navigation
+    focused: Option<usize>,
+    /// Focused button as currently shown on screen
+    displayed_focused: Option<usize>,
+    /// Button held down with the enter key
+    keyboard_pressed: Option<usize>,
+    /// Navigation keys pressed while this layout was active, so only their releases are kept
+    navigation_keys_down: fxhash::FxHashSet<u8>,
 }
 
 impl Layout {
     fn new(elements: Vec<Element>) -> Self {
         Self {
             elements,
+            rotation: Default::default(),
+            timing: ButtonTiming::from_config(&Default::default()),
+            hidden: false,
             fingers: Default::default(),
             pressed_indices: Default::default(),
+            displayed_pressed_indices: Default::default(),
+            held_buttons: Default::default(),
+            latched_indices: Default::default(),
+            last_released: Default::default(),
+            pending_key_releases: Default::default(),
+            focused: None,
+            displayed_focused: None,
+            keyboard_pressed: None,
+            navigation_keys_down: Default::default(),
+        }
+    }
+
+    /// Take over the input state of the layout this one replaces, so held keys, latched
+    /// buttons and the focus survive. Only possible if it has the same elements.
+    fn carry_over_state(&mut self, old: &mut Layout) {
+        self.focused = old
+            .focused
+            .filter(|index| self.button_indices().any(|button| button == *index));
+        if self.elements.len() != old.elements.len() {
+            return;
+        }
+        self.fingers = std::mem::take(&mut old.fingers);
+        self.pressed_indices = std::mem::take(&mut old.pressed_indices);
+        self.held_buttons = std::mem::take(&mut old.held_buttons);
+        self.latched_indices = std::mem::take(&mut old.latched_indices);
+        self.last_released = std::mem::take(&mut old.last_released);
+        self.pending_key_releases = std::mem::take(&mut old.pending_key_releases);
+        self.keyboard_pressed = old.keyboard_pressed.take();
+        self.navigation_keys_down = std::mem::take(&mut old.navigation_keys_down);
+    }
+
+    fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Mirror the layout horizontally (for left-handed players). Buttons next to each
+    /// other move as a group, so e.g. the left arrow stays left of the right one.
+    /// Needs to be done after the rotation is set.
+    fn mirrored(mut self, mirrored: bool) -> Self {
+        if !mirrored {
+            return self;
+        }
+        let (display_width, _) = self.rotation.logical_display_size();
+        let rects: Vec<common::mxcfb_rect> = self.elements.iter().map(|el| *el.rect()).collect();
+
+        // Group of every element, merging groups of elements that are close to each other
+        let mut groups: Vec<usize> = (0..rects.len()).collect();
+        for a in 0..rects.len() {
+            for b in a + 1..rects.len() {
+                if rects_touch(&grown(&rects[a], MIRROR_GROUP_GAP), &rects[b]) {
+                    let (from, to) = (groups[b], groups[a]);
+                    for group in groups.iter_mut().filter(|group| **group == from) {
+                        *group = to;
+                    }
+                }
+            }
+        }
+
+        for group in groups.iter().copied().collect::<fxhash::FxHashSet<usize>>() {
+            let indices: Vec<usize> = (0..rects.len())
+                .filter(|index| groups[*index] == group)
+                .collect();
+            // Not combined_rect, since landscape coordinates are wider than the display
+            let left = indices
+                .iter()
+                .map(|index| rects[*index].left)
+                .min()
+                .unwrap();
+            let right = indices
+                .iter()
+                .map(|index| rects[*index].left + rects[*index].width)
+                .max()
+                .unwrap();
+            let mirrored_left = display_width - right;
+            for index in indices {
+                let rect = self.elements[index].rect_mut();
+                rect.left = rect.left - left + mirrored_left;
+            }
         }
+        self
     }
 
+    /// Area on the framebuffer (not rotated) covered by this layout
     pub fn get_area(&self) -> common::mxcfb_rect {
-        combined_rect(self.elements.iter().map(|el| *el.rect()))
+        combined_rect(
+            self.elements
+                .iter()
+                .map(|el| self.rotation.to_screen_rect(el.rect())),
+        )
     }
 
-    pub fn render(&self, fb: &mut Framebuffer) {
+    pub fn render(&mut self, fb: &mut Framebuffer) {
+        if self.hidden {
+            self.displayed_focused = self.focused;
+            self.displayed_pressed_indices = self.held_indices();
+            return;
+        }
         for element in &self.elements {
             match element {
                 Element::Button {
@@ -150,18 +585,7 @@ impl Layout {
                     rect,
                     ..
                 } => {
-                    fb.draw_rect(
-                        Point2 {
-                            x: rect.left as i32 + 2,
-                            y: rect.top as i32 + 2,
-                        },
-                        Vector2 {
-                            x: rect.width - 4,
-                            y: rect.height - 4,
-                        },
-                        3,
-                        common::color::BLACK,
-                    );
+                    draw_button_border(fb, &self.rotation.to_screen_rect(rect));
 
                     let text_rect = fb.draw_text(
                         Point2 { x: 0f32, y: 500f32 },
@@ -171,7 +595,9 @@ impl Layout {
                         true,
                     );
 
-                    fb.draw_text(
+                    rotation::draw_text(
+                        fb,
+                        self.rotation,
                         Point2 {
                             x: (rect.left as f32 + (rect.width - text_rect.width) as f32 / 2.0),
                             y: (rect.top as f32 + (rect.height - text_rect.height) as f32 / 2.0)
@@ -192,7 +618,9 @@ impl Layout {
                         true,
                     );
 
-                    fb.draw_text(
+                    rotation::draw_text(
+                        fb,
+                        self.rotation,
                         Point2 {
                             x: (rect.left as f32 + (rect.width - text_rect.width) as f32 / 2.0),
                             y: (rect.top as f32 + (rect.height - text_rect.height) as f32 / 2.0)
@@ -206,9 +634,178 @@ impl Layout {
                 }
             }
         }
+
+        self.displayed_focused = self.focused;
+        if let Some(index) = self.focused {
+            self.draw_focus_ring(fb, index, true);
+        }
+
+        let held_indices = self.held_indices();
+        for &index in &held_indices {
+            self.draw_pressed_state(fb, index, true);
+        }
+        self.displayed_pressed_indices = held_indices;
+    }
+
+    /// Buttons that should be shown as held (pressed, latched or active)
+    fn held_indices(&self) -> fxhash::FxHashSet<usize> {
+        let active_indices = self
+            .elements
+            .iter()
+            .enumerate()
+            .filter_map(|(index, element)| match element {
+                Element::Button {
+                    action: ButtonAction::ChangeKeyboard { active: true, .. },
+                    ..
+                } => Some(index),
+                _ => None,
+            });
+        self.pressed_indices
+            .union(&self.latched_indices)
+            .copied()
+            .chain(active_indices)
+            .collect()
+    }
+
+    /// Redraw buttons whose pressed or focused state changed since the last call
+    /// and refresh them with a fast waveform. Only touching buttons get refreshed together.
+    pub fn update_pressed_buttons(&mut self, fb: &mut Framebuffer) {
+        if self.hidden {
+            self.displayed_focused = self.focused;
+            self.displayed_pressed_indices = self.held_indices();
+            return;
+        }
+        let held_indices = self.held_indices();
+        let changed: Vec<(usize, bool)> = held_indices
+            .symmetric_difference(&self.displayed_pressed_indices)
+            .map(|index| (*index, held_indices.contains(index)))
+            .collect();
+        let mut changed_rects: Vec<common::mxcfb_rect> = changed
+            .into_iter()
+            .filter_map(|(index, pressed)| self.draw_pressed_state(fb, index, pressed))
+            .collect();
+        self.displayed_pressed_indices = held_indices;
+
+        if self.focused != self.displayed_focused {
+            if let Some(index) = self.displayed_focused.take() {
+                changed_rects.extend(self.draw_focus_ring(fb, index, false));
+            }
+            if let Some(index) = self.focused {
+                changed_rects.extend(self.draw_focus_ring(fb, index, true));
+            }
+            self.displayed_focused = self.focused;
+        }
+
+        // Combining buttons far apart (e.g. both bands in fullscreen) would refresh
+        // everything between them
+        let mut areas: Vec<common::mxcfb_rect> = vec![];
+        for rect in changed_rects {
+            match areas.iter_mut().find(|area| rects_touch(area, &rect)) {
+                Some(area) => *area = combined_rect([*area, rect].iter().copied()),
+                None => areas.push(rect),
+            }
+        }
+        for area in &areas {
+            fb.partial_refresh(
+                area,
+                PartialRefreshMode::Async,
+                common::waveform_mode::WAVEFORM_MODE_DU,
+                common::display_temp::TEMP_USE_REMARKABLE_DRAW,
+                common::dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+                0,
+                false,
+            );
+        }
+    }
+
+    /// Draw or remove the thick border of a held button. Returns the changed area.
+    fn draw_pressed_state(
+        &self,
+        fb: &mut Framebuffer,
+        index: usize,
+        pressed: bool,
+    ) -> Option<common::mxcfb_rect> {
+        let screen_rect = match &self.elements[index] {
+            Element::Button { rect, .. } => self.rotation.to_screen_rect(rect),
+            Element::Text { .. } => return None,
+        };
+
+        let color = if pressed {
+            common::color::BLACK
+        } else {
+            common::color::WHITE
+        };
+        let (left, top) = (screen_rect.left as i32 + 2, screen_rect.top as i32 + 2);
+        let (width, height) = (screen_rect.width - 4, screen_rect.height - 4);
+        let thickness = PRESSED_BORDER_WIDTH.min(width / 2).min(height / 2);
+        let bands = [
+            (left, top, width, thickness),
+            (left, top + (height - thickness) as i32, width, thickness),
+            (left, top, thickness, height),
+            (left + (width - thickness) as i32, top, thickness, height),
+        ];
+        for (x, y, width, height) in bands.iter() {
+            fb.fill_rect(
+                Point2 { x: *x, y: *y },
+                Vector2 {
+                    x: *width,
+                    y: *height,
+                },
+                color,
+            );
+        }
+        if !pressed {
+            draw_button_border(fb, &screen_rect);
+            if self.displayed_focused == Some(index) {
+                self.draw_focus_ring(fb, index, true);
+            }
+        }
+
+        Some(screen_rect)
+    }
+
+    /// Draw or remove the ring inside a button that has the keyboard focus.
+    /// Returns the changed area.
+    fn draw_focus_ring(
+        &self,
+        fb: &mut Framebuffer,
+        index: usize,
+        focused: bool,
+    ) -> Option<common::mxcfb_rect> {
+        let screen_rect = match &self.elements[index] {
+            Element::Button { rect, .. } => self.rotation.to_screen_rect(rect),
+            Element::Text { .. } => return None,
+        };
+        if !focused && self.displayed_pressed_indices.contains(&index) {
+            // The ring is covered by the pressed border, which must not get a gap
+            return Some(screen_rect);
+        }
+
+        let color = if focused {
+            common::color::BLACK
+        } else {
+            common::color::WHITE
+        };
+        fb.draw_rect(
+            Point2 {
+                x: (screen_rect.left + FOCUS_RING_INSET) as i32,
+                y: (screen_rect.top + FOCUS_RING_INSET) as i32,
+            },
+            Vector2 {
+                x: screen_rect.width - FOCUS_RING_INSET * 2,
+                y: screen_rect.height - FOCUS_RING_INSET * 2,
+            },
+            FOCUS_RING_WIDTH,
+            color,
+        );
+
+        Some(screen_rect)
     }
 
     pub fn clear(&self, fb: &mut Framebuffer) {
+        if self.hidden {
+            return; // Would clear what covers it
+        }
         // Turn area white
         fb.restore_region(
             self.get_area(),
@@ -218,7 +815,7 @@ impl Layout {
     }
 
     pub fn handle_input(&mut self, event: InputEvent) -> Vec<InputOutcome> {
-        let mut outcomes = match event {
+        let outcomes = match event {
             InputEvent::MultitouchEvent { event } => match event {
                 MultitouchEvent::Press { finger } => {
                     self.fingers.insert(finger.tracking_id, finger);
@@ -237,12 +834,106 @@ impl Layout {
             _ => unimplemented!(),
         };
 
-        // Fake all fingers released before switching a layout to prevent stuck keys
+        self.release_before_switching(outcomes)
+    }
+
+    /// Move the focus with the arrow keys or tab and press the focused button with enter.
+    /// Other keys are passed on to the game.
+    pub fn handle_key(&mut self, keydata: KeyData) -> Vec<InputOutcome> {
+        let key = keydata.key;
+        let direction = if key == *keys::KEY_UP {
+            Some((0, -1))
+        } else if key == *keys::KEY_DOWN {
+            Some((0, 1))
+        } else if key == *keys::KEY_LEFT {
+            Some((-1, 0))
+        } else if key == *keys::KEY_RIGHT {
+            Some((1, 0))
+        } else {
+            None
+        };
+        // Tab is the same key as the automap in the game
+        if direction.is_none() && key != KEY_MAP && key != keys::KEY_ENTER {
+            return vec![InputOutcome::KeyData(keydata)];
+        }
+
+        if !keydata.pressed {
+            if !self.navigation_keys_down.remove(&key) {
+                // Pressed in another layout, so the game needs the release
+                return vec![InputOutcome::KeyData(keydata)];
+            }
+            if key == keys::KEY_ENTER && self.keyboard_pressed.take().is_some() {
+                let outcomes = self.process_fingers();
+                return self.release_before_switching(outcomes);
+            }
+            return vec![];
+        }
+        self.navigation_keys_down.insert(key);
+
+        if key == keys::KEY_ENTER {
+            if self.focused.is_some() && self.keyboard_pressed.is_none() {
+                self.keyboard_pressed = self.focused;
+                let outcomes = self.process_fingers();
+                return self.release_before_switching(outcomes);
+            }
+            return vec![];
+        }
+
+        let buttons: Vec<usize> = self.button_indices().collect();
+        self.focused = match (self.focused, direction) {
+            (None, _) => buttons.first().copied(),
+            (Some(current), None) => buttons
+                .iter()
+                .position(|index| *index == current)
+                .map(|position| buttons[(position + 1) % buttons.len()]),
+            (Some(current), Some((dx, dy))) => self.neighbour(current, dx, dy).or(Some(current)),
+        };
+        vec![]
+    }
+
+    fn button_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.elements
+            .iter()
+            .enumerate()
+            .filter(|(_, element)| matches!(element, Element::Button { .. }))
+            .map(|(index, _)| index)
+    }
+
+    /// Closest button in the direction (dx, dy), preferring ones that are in line
+    fn neighbour(&self, from: usize, dx: i32, dy: i32) -> Option<usize> {
+        let center = |index: usize| {
+            let rect = self.elements[index].rect();
+            (
+                rect.left as i32 + rect.width as i32 / 2,
+                rect.top as i32 + rect.height as i32 / 2,
+            )
+        };
+        let (x, y) = center(from);
+        self.button_indices()
+            .filter_map(|index| {
+                let (other_x, other_y) = center(index);
+                let along = (other_x - x) * dx + (other_y - y) * dy;
+                let across = ((other_x - x) * dy).abs() + ((other_y - y) * dx).abs();
+                if along <= 0 {
+                    return None;
+                }
+                Some((along + across * 2, index))
+            })
+            .min()
+            .map(|(_, index)| index)
+    }
+
+    /// Fake all fingers (and the enter key) released before switching a layout
+    /// to prevent stuck keys
+    fn release_before_switching(&mut self, mut outcomes: Vec<InputOutcome>) -> Vec<InputOutcome> {
         let mut i = 0;
         while i < outcomes.len() {
-            if let InputOutcome::SwitchLayout(_) = &outcomes[i] {
-                self.fingers.clear();
-                for outcome in self.process_fingers() {
+            if let InputOutcome::SwitchLayout(_)
+            | InputOutcome::EnterFullscreen
+            | InputOutcome::ExitFullscreen
+            | InputOutcome::Exit = &outcomes[i]
+            {
+                for outcome in self.release_all() {
                     outcomes.insert(i, outcome);
                     i += 1;
                 }
@@ -253,32 +944,101 @@ impl Layout {
         outcomes
     }
 
+    /// Fake all fingers and the enter key released and unlatch toggled keys
+    fn release_all(&mut self) -> Vec<InputOutcome> {
+        self.fingers.clear();
+        self.keyboard_pressed = None;
+        let mut releases = self.process_fingers();
+        // Latched keys (Run, Hold fire) would stay down in the game
+        for index in self.latched_indices.drain() {
+            if let Element::Button {
+                action: ButtonAction::ToggleDoomKey(key),
+                ..
+            } = &self.elements[index]
+            {
+                releases.push(key_outcome(*key, false));
+            }
+        }
+        // Taps waiting for their release would not be ticked anymore
+        releases.extend(
+            self.pending_key_releases
+                .drain(..)
+                .map(|(_, key)| key_outcome(key, false)),
+        );
+        releases
+    }
+
     fn process_fingers(&mut self) -> Vec<InputOutcome> {
         let mut outcomes = vec![];
         let last_pressed_indices = self.pressed_indices.clone();
 
         self.pressed_indices.clear();
         for finger in self.fingers.values() {
+            let (x, y) = self
+                .rotation
+                .from_screen_point(finger.pos.x as u32, finger.pos.y as u32);
             for (i, element) in self.elements.iter().enumerate() {
-                if finger.pos.x as u32 >= element.rect().left
-                    && finger.pos.x as u32 <= element.rect().left + element.rect().width
-                    && finger.pos.y as u32 >= element.rect().top
-                    && finger.pos.y as u32 <= element.rect().top + element.rect().height
+                if x >= element.rect().left
+                    && x <= element.rect().left + element.rect().width
+                    && y >= element.rect().top
+                    && y <= element.rect().top + element.rect().height
                 {
                     self.pressed_indices.insert(i);
                     break;
                 }
             }
         }
+        if let Some(index) = self.keyboard_pressed {
+            self.pressed_indices.insert(index);
+        }
+
+        let now = Instant::now();
+        let key_up_indices: Vec<usize> = last_pressed_indices
+            .difference(&self.pressed_indices)
+            .copied()
+            .collect();
+        let key_down_indices: Vec<usize> = self
+            .pressed_indices
+            .difference(&last_pressed_indices)
+            .copied()
+            .collect();
 
-        for key_up_index in last_pressed_indices.difference(&self.pressed_indices) {
-            if let Element::Button { action, .. } = &self.elements[*key_up_index] {
+        for key_up_index in key_up_indices {
+            let held = self.held_buttons.remove(&key_up_index);
+            if let Some(key) = held.as_ref().and_then(|held| held.key_down) {
+                outcomes.push(key_outcome(key, false));
+            }
+
+            if let Element::Button { action, .. } = &self.elements[key_up_index] {
                 match action {
-                    ButtonAction::DoomKey(key) => {
-                        outcomes.push(InputOutcome::KeyData(KeyData {
-                            key: *key,
-                            pressed: false,
-                        }));
+                    ButtonAction::DoomKey(_) => {}
+                    ButtonAction::ToggleDoomKey(_) => {}
+                    ButtonAction::LongPressDoomKey { key, .. } => {
+                        if held.map(|held| held.key_down.is_none()).unwrap_or(false) {
+                            // Released before the long press happened => tap
+                            outcomes.push(key_outcome(*key, true));
+                            self.pending_key_releases
+                                .push((now + TAP_RELEASE_DELAY, *key));
+                        }
+                    }
+                    ButtonAction::DoubleTapDoomKey { .. } => {
+                        // Only a single tap can start a double tap
+                        if self.last_released.remove(&key_up_index).is_none() {
+                            self.last_released.insert(key_up_index, now);
+                        }
+                    }
+                    ButtonAction::RepeatDoomKey(_) => {}
+                    ButtonAction::TypeKey { modifiers, .. } => {
+                        for modifier in modifiers.iter().rev() {
+                            outcomes.push(key_outcome(*modifier, false));
+                        }
+                        outcomes.push(InputOutcome::ChangeKeyboard(KeyboardState::release_sticky));
+                    }
+                    ButtonAction::ChangeKeyboard { change, .. } => {
+                        outcomes.push(InputOutcome::ChangeKeyboard(*change));
+                    }
+                    ButtonAction::Macro(macro_) => {
+                        outcomes.push(InputOutcome::PlayMacro(macro_.clone()));
                     }
                     ButtonAction::Function(func) => {
                         func();
@@ -289,41 +1049,177 @@ impl Layout {
                     ButtonAction::EnterFullscreen => {
                         outcomes.push(InputOutcome::EnterFullscreen);
                     }
+                    ButtonAction::ExitFullscreen => {
+                        outcomes.push(InputOutcome::ExitFullscreen);
+                    }
+                    ButtonAction::Exit => {
+                        outcomes.push(InputOutcome::Exit);
+                    }
+                    ButtonAction::LoadGame(slot) => {
+                        outcomes.push(InputOutcome::SwitchLayout(LayoutId::Controls));
+                        outcomes.push(InputOutcome::PlayMacro(crate::saves::load_macro(*slot)));
+                    }
+                    ButtonAction::ChangeSaves(change) => {
+                        change();
+                        outcomes.push(InputOutcome::SavesChanged);
+                    }
+                    ButtonAction::ConfirmSavesChange(change) => {
+                        outcomes.push(InputOutcome::ConfirmSavesChange(*change));
+                    }
+                    ButtonAction::ApplySavesChange(change) => {
+                        change.apply();
+                        // Lists the saves again
+                        outcomes.push(InputOutcome::SwitchLayout(LayoutId::Saves));
+                    }
+                    ButtonAction::ChangeSetting(change) => {
+                        let mut config = CONFIG.lock().unwrap();
+                        change(&mut config);
+                        config.save();
+                        outcomes.push(InputOutcome::SettingsChanged);
+                    }
                 }
             }
         }
 
-        for key_down_index in self.pressed_indices.difference(&last_pressed_indices) {
-            if let Element::Button { action, .. } = &self.elements[*key_down_index] {
+        for key_down_index in key_down_indices {
+            let mut held = HeldButton {
+                since: now,
+                key_down: None,
+                last_repeat: None,
+            };
+
+            if let Element::Button { action, .. } = &self.elements[key_down_index] {
                 match action {
-                    ButtonAction::DoomKey(key) => {
-                        outcomes.push(InputOutcome::KeyData(KeyData {
-                            key: *key,
-                            pressed: true,
-                        }));
+                    ButtonAction::DoomKey(key) | ButtonAction::RepeatDoomKey(key) => {
+                        held.key_down = Some(*key);
+                    }
+                    ButtonAction::ToggleDoomKey(key) => {
+                        let latched = !self.latched_indices.remove(&key_down_index);
+                        if latched {
+                            self.latched_indices.insert(key_down_index);
+                        }
+                        outcomes.push(key_outcome(*key, latched));
+                    }
+                    ButtonAction::TypeKey { key, modifiers } => {
+                        for modifier in modifiers {
+                            outcomes.push(key_outcome(*modifier, true));
+                        }
+                        held.key_down = Some(*key);
+                    }
+                    ButtonAction::ChangeKeyboard { .. } => {}
+                    ButtonAction::LongPressDoomKey { .. } => {} // Decided on release or in tick()
+                    ButtonAction::DoubleTapDoomKey {
+                        key,
+                        double_tap_key,
+                    } => {
+                        let is_double_tap = self
+                            .last_released
+                            .get(&key_down_index)
+                            .map(|released| released.elapsed() <= self.timing.double_tap)
+                            .unwrap_or(false);
+                        if !is_double_tap {
+                            self.last_released.remove(&key_down_index);
+                        }
+                        held.key_down = Some(if is_double_tap { *double_tap_key } else { *key });
                     }
 
+                    ButtonAction::Macro(_) => {}
                     ButtonAction::Function(_) => {}
                     ButtonAction::SwitchLayout(_) => {}
                     ButtonAction::EnterFullscreen => {}
+                    ButtonAction::ExitFullscreen => {}
+                    ButtonAction::Exit => {}
+                    ButtonAction::LoadGame(_) => {}
+                    ButtonAction::ChangeSaves(_) => {}
+                    ButtonAction::ConfirmSavesChange(_) => {}
+                    ButtonAction::ApplySavesChange(_) => {}
+                    ButtonAction::ChangeSetting(_) => {}
+                }
+            }
+
+            if let Some(key) = held.key_down {
+                outcomes.push(key_outcome(key, true));
+            }
+            self.held_buttons.insert(key_down_index, held);
+        }
+
+        outcomes
+    }
+
+    /// Handle time based behaviours of buttons. Should be called about every [`TICK_INTERVAL`].
+    pub fn tick(&mut self) -> Vec<InputOutcome> {
+        let mut outcomes = vec![];
+        let now = Instant::now();
+
+        for (index, held) in self.held_buttons.iter_mut() {
+            if let Element::Button { action, .. } = &self.elements[*index] {
+                match action {
+                    ButtonAction::LongPressDoomKey { long_press_key, .. } => {
+                        if held.key_down.is_none()
+                            && now.duration_since(held.since) >= self.timing.long_press
+                        {
+                            held.key_down = Some(*long_press_key);
+                            outcomes.push(key_outcome(*long_press_key, true));
+                        }
+                    }
+                    ButtonAction::RepeatDoomKey(key) => {
+                        let last = held.last_repeat.unwrap_or(held.since);
+                        let wait = if held.last_repeat.is_some() {
+                            self.timing.repeat_interval
+                        } else {
+                            self.timing.repeat_delay
+                        };
+                        if now.duration_since(last) >= wait {
+                            // Doom acts on each key down event (e.g. moving in menus)
+                            held.last_repeat = Some(now);
+                            outcomes.push(key_outcome(*key, true));
+                        }
+                    }
+                    _ => {}
                 }
             }
         }
 
+        let (due, pending): (Vec<(Instant, u8)>, Vec<(Instant, u8)>) = self
+            .pending_key_releases
+            .drain(..)
+            .partition(|(release_at, _)| *release_at <= now);
+        self.pending_key_releases = pending;
+        outcomes.extend(due.into_iter().map(|(_, key)| key_outcome(key, false)));
+
         outcomes
     }
 }
 
+fn key_outcome(key: u8, pressed: bool) -> InputOutcome {
+    InputOutcome::KeyData(KeyData { key, pressed })
+}
+
+fn draw_button_border(fb: &mut Framebuffer, screen_rect: &common::mxcfb_rect) {
+    fb.draw_rect(
+        Point2 {
+            x: screen_rect.left as i32 + 2,
+            y: screen_rect.top as i32 + 2,
+        },
+        Vector2 {
+            x: screen_rect.width - 4,
+            y: screen_rect.height - 4,
+        },
+        3,
+        common::color::BLACK,
+    );
+}
+
 enum Element {
     Button {
         rect: common::mxcfb_rect,
-        label: &'static str,
+        label: String,
         label_size: f32,
         action: ButtonAction,
     },
     Text {
         rect: common::mxcfb_rect,
-        text: &'static str,
+        text: String,
         size: f32,
     },
 }
@@ -335,11 +1231,100 @@ impl Element {
             Element::Text { rect, .. } => rect,
         }
     }
+
+    fn rect_mut(&mut self) -> &mut common::mxcfb_rect {
+        match self {
+            Element::Button { rect, .. } => rect,
+            Element::Text { rect, .. } => rect,
+        }
+    }
 }
 
 enum ButtonAction {
     DoomKey(u8),
+    /// Latching: First press holds the key down, second press releases it
+    ToggleDoomKey(u8),
+    /// Taps send `key`, holding the button sends `long_press_key` instead
+    LongPressDoomKey {
+        key: u8,
+        long_press_key: u8,
+    },
+    /// Like DoomKey, but the second press of a double tap sends `double_tap_key` instead
+    DoubleTapDoomKey {
+        key: u8,
+        double_tap_key: u8,
+    },
+    /// Like DoomKey, but holding it sends repeated key presses (e.g. for menus)
+    RepeatDoomKey(u8),
+    /// Key of the on-screen keyboard. The `modifiers` are held down with it and sticky
+    /// modifiers get released afterwards.
+    TypeKey {
+        key: u8,
+        modifiers: Vec<u8>,
+    },
+    /// Changes the modifiers or page of the on-screen keyboard. Shown as held if `active`.
+    ChangeKeyboard {
+        change: fn(&mut KeyboardState),
+        active: bool,
+    },
+    /// Types a sequence of keys when released
+    Macro(Macro),
     Function(Box<dyn Fn()>),
     SwitchLayout(LayoutId),
     EnterFullscreen,
+    ExitFullscreen,
+    Exit,
+    /// Loads the slot and goes to the controls
+    LoadGame(usize),
+    /// Modifies savegame files (delete, backup, ...)
+    ChangeSaves(Box<dyn Fn()>),
+    /// Goes to the ConfirmSaves layout for the change
+    ConfirmSavesChange(SavesChange),
+    /// Applies the confirmed change and goes back to the saves
+    ApplySavesChange(SavesChange),
+    ChangeSetting(Box<dyn Fn(&mut Config)>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button_rect(layout: &Layout, wanted: &str) -> common::mxcfb_rect {
+        layout
+            .elements
+            .iter()
+            .find_map(|element| match element {
+                Element::Button { label, rect, .. } if label == wanted => Some(*rect),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn left_handed_layout(id: LayoutId) -> Layout {
+        let config = Config {
+            left_handed: true,
+            ..Default::default()
+        };
+        LayoutManager::create_layout(id, &config, &Default::default(), None)
+    }
+
+    #[test]
+    fn mirroring_keeps_the_arrows_in_order() {
+        for id in [LayoutId::Controls, LayoutId::Fullscreen] {
+            let layout = left_handed_layout(id);
+            assert!(button_rect(&layout, "<").left < button_rect(&layout, ">").left);
+        }
+    }
+
+    #[test]
+    fn mirroring_keeps_pairs_in_order() {
+        let layout = left_handed_layout(LayoutId::Controls);
+        assert!(button_rect(&layout, "Hold fire").left < button_rect(&layout, "Run").left);
+        assert!(
+            button_rect(&layout, "Enter (2x: Yes)").left
+                < button_rect(&layout, "Use (hold: Map)").left
+        );
+        // The groups still move to the other side
+        assert!(button_rect(&layout, "<").left < 1404 / 2);
+    }
 }
@@ -0,0 +1,141 @@
+use super::{ButtonAction, Element, Layout, LayoutId, SavesChange};
+use crate::clock::LocalTime;
+use crate::saves;
+use libremarkable::framebuffer::common;
+
+/// Longer names get cut off to leave space for the map and date
+const MAX_DESCRIPTION_LENGTH: usize = 12;
+
+pub fn create() -> Layout {
+    let title = match saves::iwad_name() {
+        Some(iwad) => format!("Saves ({iwad})"),
+        None => "Saves".to_owned(),
+    };
+    let mut buttons = vec![
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 100,
+                top: 1400 - 300 - 10 - 10,
+                width: 100,
+                height: 50,
+            },
+            label: "Back".into(),
+            label_size: 25.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Settings),
+        },
+        Element::Text {
+            rect: common::mxcfb_rect {
+                left: 0,
+                top: 1400 - 300 - 10 - 10,
+                width: common::DISPLAYWIDTH as u32,
+                height: 100,
+            },
+            text: title,
+            size: 100.0,
+        },
+    ];
+
+    for slot in 0..saves::SLOTS {
+        let top = 1400 - 300 - 10 + 100 + 10 + (80 + 10) * slot as u32;
+        let save = match saves::read_slot(slot) {
+            Some(save) => save,
+            None => {
+                buttons.push(Element::Text {
+                    rect: common::mxcfb_rect {
+                        left: 62,
+                        top,
+                        width: 740,
+                        height: 80,
+                    },
+                    text: format!("{}. Empty", slot + 1),
+                    size: 30.0,
+                });
+                continue;
+            }
+        };
+
+        let mut description: String = save
+            .description
+            .chars()
+            .take(MAX_DESCRIPTION_LENGTH)
+            .collect();
+        if description.len() < save.description.len() {
+            description.push_str("...");
+        }
+        let date = match save.modified {
+            Some(modified) => LocalTime::from_system_time(modified).date_time(),
+            None => String::new(),
+        };
+        buttons.push(Element::Text {
+            rect: common::mxcfb_rect {
+                left: 62,
+                top,
+                width: 740,
+                height: 80,
+            },
+            text: format!("{}. {}  {}  {}", slot + 1, description, save.map, date),
+            size: 30.0,
+        });
+        buttons.push(Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62 + 740 + 10,
+                top,
+                width: 170,
+                height: 80,
+            },
+            label: "Load".into(),
+            label_size: 35.0,
+            action: ButtonAction::LoadGame(slot),
+        });
+        buttons.push(Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62 + 740 + 10 + 170 + 10,
+                top,
+                width: 170,
+                height: 80,
+            },
+            label: "Copy".into(),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSaves(Box::new(move || saves::duplicate(slot))),
+        });
+        buttons.push(Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62 + 740 + 10 + (170 + 10) * 2,
+                top,
+                width: 170,
+                height: 80,
+            },
+            label: "Delete".into(),
+            label_size: 35.0,
+            action: ButtonAction::ConfirmSavesChange(SavesChange::Delete(slot)),
+        });
+    }
+
+    let top = 1400 - 300 - 10 + 100 + 10 + (80 + 10) * saves::SLOTS as u32 + 10;
+    buttons.push(Element::Button {
+        rect: common::mxcfb_rect {
+            left: 62,
+            top,
+            width: 400,
+            height: 90,
+        },
+        label: "Back up all".into(),
+        label_size: 40.0,
+        action: ButtonAction::ChangeSaves(Box::new(saves::backup)),
+    });
+    if let Some(backup) = saves::latest_backup() {
+        buttons.push(Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 700,
+                top,
+                width: 700,
+                height: 90,
+            },
+            label: format!("Restore {}", backup.replace('_', " ")),
+            label_size: 40.0,
+            action: ButtonAction::ConfirmSavesChange(SavesChange::RestoreLatestBackup),
+        });
+    }
+
+    Layout::new(buttons)
+}
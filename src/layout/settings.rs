@@ -1,8 +1,20 @@
-use super::{ButtonAction, Element, Layout, LayoutId};
+use super::{ButtonAction, Element, KeyboardVariant, Layout, LayoutId};
+use crate::config::Config;
+use crate::evdev_keyboard::GrabMode;
+use crate::governor::FpsLimit;
+use crate::rotation::Rotation;
 use crate::FB;
 use libremarkable::framebuffer::{common, FramebufferRefresh};
 
-pub fn create() -> Layout {
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+pub fn create(config: &Config) -> Layout {
     let buttons = vec![
         Element::Button {
             rect: common::mxcfb_rect {
@@ -11,10 +23,69 @@ pub fn create() -> Layout {
                 width: 100,
                 height: 50,
             },
-            label: "Back",
+            label: "Back".into(),
             label_size: 25.0,
             action: ButtonAction::SwitchLayout(LayoutId::Controls),
         },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62,
+                top: 1400 - 300 - 10 - 10,
+                width: 100,
+                height: 50,
+            },
+            label: "Saves".into(),
+            label_size: 25.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Saves),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62 + 100 + 10,
+                top: 1400 - 300 - 10 - 10,
+                width: 150,
+                height: 50,
+            },
+            label: format!("Stats: {}", on_off(config.perf_overlay)),
+            label_size: 25.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.perf_overlay = !config.perf_overlay;
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62 + 100 + 10 + 150 + 10,
+                top: 1400 - 300 - 10 - 10,
+                width: 150,
+                height: 50,
+            },
+            label: format!("Scale: {}", config.render_scale.name()),
+            label_size: 25.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.render_scale = config.render_scale.next();
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 100 - 10 - 150,
+                top: 1400 - 300 - 10 - 10,
+                width: 150,
+                height: 50,
+            },
+            label: match config.fps_limit {
+                FpsLimit::Auto => "FPS: Auto".to_owned(),
+                FpsLimit::Fixed(fps) => format!("FPS: {fps}"),
+            },
+            label_size: 25.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.fps_limit = match config.fps_limit {
+                    FpsLimit::Auto => FpsLimit::Fixed(3),
+                    FpsLimit::Fixed(fps) if fps < 5 => FpsLimit::Fixed(5),
+                    FpsLimit::Fixed(fps) if fps < 10 => FpsLimit::Fixed(10),
+                    FpsLimit::Fixed(fps) if fps < 15 => FpsLimit::Fixed(15),
+                    FpsLimit::Fixed(_) => FpsLimit::Auto,
+                };
+            })),
+        },
         Element::Text {
             rect: common::mxcfb_rect {
                 left: 0,
@@ -22,7 +93,7 @@ pub fn create() -> Layout {
                 width: common::DISPLAYWIDTH as u32,
                 height: 100,
             },
-            text: "Settings",
+            text: "Settings".into(),
             size: 100.0,
         },
         Element::Button {
@@ -32,7 +103,7 @@ pub fn create() -> Layout {
                 width: 400,
                 height: 100,
             },
-            label: "Full refresh",
+            label: "Full refresh".into(),
             label_size: 50.0,
             action: ButtonAction::Function(Box::new(|| {
                 FB.lock().unwrap().full_refresh(
@@ -51,7 +122,7 @@ pub fn create() -> Layout {
                 width: 400,
                 height: 100,
             },
-            label: "Fullscreen",
+            label: "Fullscreen".into(),
             label_size: 50.0,
             action: ButtonAction::SwitchLayout(LayoutId::ConfirmFullscreen),
         },
@@ -62,10 +133,151 @@ pub fn create() -> Layout {
                 width: 400,
                 height: 100,
             },
-            label: "Exit",
+            label: "Exit".into(),
             label_size: 50.0,
             action: ButtonAction::SwitchLayout(LayoutId::ConfirmExit),
         },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 3,
+                width: 400,
+                height: 100,
+            },
+            label: "Cheats & macros".into(),
+            label_size: 50.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Cheats),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 4,
+                width: 400,
+                height: 100,
+            },
+            label: "Input devices".into(),
+            label_size: 50.0,
+            action: ButtonAction::SwitchLayout(LayoutId::Devices),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 62,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 5,
+                width: 400,
+                height: 100,
+            },
+            label: format!("Auto pause: {}", on_off(config.auto_pause)),
+            label_size: 50.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.auto_pause = !config.auto_pause;
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 500,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 0,
+                width: 500,
+                height: 100,
+            },
+            label: match config.portrait_rotation {
+                Rotation::UpsideDown => "Portrait: Upside down",
+                _ => "Portrait: Normal",
+            }
+            .into(),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.portrait_rotation = match config.portrait_rotation {
+                    Rotation::UpsideDown => Rotation::None,
+                    _ => Rotation::UpsideDown,
+                };
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 500,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 1,
+                width: 500,
+                height: 100,
+            },
+            // Rotating the content clockwise means turning the device to the left
+            label: match config.fullscreen_rotation {
+                Rotation::CounterClockwise => "Landscape: Turned right",
+                _ => "Landscape: Turned left",
+            }
+            .into(),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.fullscreen_rotation = match config.fullscreen_rotation {
+                    Rotation::CounterClockwise => Rotation::Clockwise,
+                    _ => Rotation::CounterClockwise,
+                };
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 500,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 2,
+                width: 500,
+                height: 100,
+            },
+            label: format!("Left-handed: {}", on_off(config.left_handed)),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.left_handed = !config.left_handed;
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 500,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 3,
+                width: 500,
+                height: 100,
+            },
+            label: match config.grab_keyboards {
+                GrabMode::Off => "Grab keyboards: Off",
+                GrabMode::Fullscreen => "Grab keyboards: Fullscreen",
+                GrabMode::Always => "Grab keyboards: Always",
+            }
+            .into(),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.grab_keyboards = match config.grab_keyboards {
+                    GrabMode::Off => GrabMode::Fullscreen,
+                    GrabMode::Fullscreen => GrabMode::Always,
+                    GrabMode::Always => GrabMode::Off,
+                };
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 500,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 4,
+                width: 500,
+                height: 100,
+            },
+            label: format!("Keyboard: {}", config.keyboard_variant.name()),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.keyboard_variant = match config.keyboard_variant {
+                    KeyboardVariant::Qwerty => KeyboardVariant::Qwertz,
+                    KeyboardVariant::Qwertz => KeyboardVariant::Azerty,
+                    KeyboardVariant::Azerty => KeyboardVariant::Qwerty,
+                };
+            })),
+        },
+        Element::Button {
+            rect: common::mxcfb_rect {
+                left: 1404 - 62 - 500,
+                top: 1400 - 300 - 10 + 100 + 10 + (100 + 10) * 5,
+                width: 500,
+                height: 100,
+            },
+            label: format!("Autosave: {}", on_off(config.autosave)),
+            label_size: 35.0,
+            action: ButtonAction::ChangeSetting(Box::new(|config| {
+                config.autosave = !config.autosave;
+            })),
+        },
     ];
 
     Layout::new(buttons)
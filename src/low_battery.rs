@@ -0,0 +1,160 @@
+//! Warnings and protective actions when the battery runs low. Only decides what to do,
+//! so it can be driven by any battery source.
+
+use crate::battery::{BatteryStatus, ChargeState};
+
+/// Ordered by severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    /// Show a toast
+    Warn,
+    /// Pause the game and autosave
+    PauseAndSave,
+    /// Shut down cleanly
+    Shutdown,
+}
+
+/// Battery percentages at which each action happens (0 disables it)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    pub warn: u8,
+    pub save: u8,
+    pub shutdown: u8,
+}
+
+pub struct Monitor {
+    thresholds: Thresholds,
+    /// Most severe action done since the battery was last charged or above all thresholds
+    done: Option<Action>,
+}
+
+impl Monitor {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            done: None,
+        }
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: Thresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// The action to do now. Each action happens only once while discharging.
+    /// If several thresholds got crossed at once, only the most severe one is returned.
+    pub fn check(&mut self, battery: BatteryStatus) -> Option<Action> {
+        if matches!(battery.state, ChargeState::Charging | ChargeState::Full) {
+            self.done = None;
+            return None;
+        }
+
+        let reached = |threshold: u8| threshold > 0 && battery.percentage <= threshold;
+        let action = if reached(self.thresholds.shutdown) {
+            Action::Shutdown
+        } else if reached(self.thresholds.save) {
+            Action::PauseAndSave
+        } else if reached(self.thresholds.warn) {
+            Action::Warn
+        } else {
+            self.done = None;
+            return None;
+        };
+
+        if matches!(self.done, Some(done) if done >= action) {
+            return None;
+        }
+        self.done = Some(action);
+        Some(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: Thresholds = Thresholds {
+        warn: 20,
+        save: 10,
+        shutdown: 5,
+    };
+
+    fn discharging(percentage: u8) -> BatteryStatus {
+        BatteryStatus {
+            percentage,
+            state: ChargeState::Discharging,
+        }
+    }
+
+    fn charging(percentage: u8) -> BatteryStatus {
+        BatteryStatus {
+            percentage,
+            state: ChargeState::Charging,
+        }
+    }
+
+    #[test]
+    fn each_threshold_fires_once() {
+        let mut monitor = Monitor::new(THRESHOLDS);
+        assert_eq!(monitor.check(discharging(21)), None);
+        assert_eq!(monitor.check(discharging(20)), Some(Action::Warn));
+        assert_eq!(monitor.check(discharging(19)), None);
+        assert_eq!(monitor.check(discharging(10)), Some(Action::PauseAndSave));
+        assert_eq!(monitor.check(discharging(9)), None);
+        assert_eq!(monitor.check(discharging(5)), Some(Action::Shutdown));
+        assert_eq!(monitor.check(discharging(4)), None);
+    }
+
+    #[test]
+    fn jumps_to_the_most_severe_action() {
+        let mut monitor = Monitor::new(THRESHOLDS);
+        assert_eq!(monitor.check(discharging(8)), Some(Action::PauseAndSave));
+        assert_eq!(monitor.check(discharging(15)), None);
+
+        let mut monitor = Monitor::new(THRESHOLDS);
+        assert_eq!(monitor.check(discharging(3)), Some(Action::Shutdown));
+        assert_eq!(monitor.check(discharging(8)), None);
+    }
+
+    #[test]
+    fn charging_rearms() {
+        let mut monitor = Monitor::new(THRESHOLDS);
+        assert_eq!(monitor.check(discharging(20)), Some(Action::Warn));
+        assert_eq!(monitor.check(charging(20)), None);
+        assert_eq!(monitor.check(discharging(20)), Some(Action::Warn));
+
+        let full = BatteryStatus {
+            percentage: 20,
+            state: ChargeState::Full,
+        };
+        assert_eq!(monitor.check(full), None);
+        assert_eq!(monitor.check(discharging(20)), Some(Action::Warn));
+    }
+
+    #[test]
+    fn rising_above_all_thresholds_rearms() {
+        let mut monitor = Monitor::new(THRESHOLDS);
+        assert_eq!(monitor.check(discharging(10)), Some(Action::PauseAndSave));
+        assert_eq!(monitor.check(discharging(21)), None);
+        assert_eq!(monitor.check(discharging(20)), Some(Action::Warn));
+        assert_eq!(monitor.check(discharging(10)), Some(Action::PauseAndSave));
+    }
+
+    #[test]
+    fn zero_disables_an_action() {
+        let mut monitor = Monitor::new(Thresholds {
+            warn: 20,
+            save: 0,
+            shutdown: 5,
+        });
+        assert_eq!(monitor.check(discharging(20)), Some(Action::Warn));
+        assert_eq!(monitor.check(discharging(10)), None);
+        assert_eq!(monitor.check(discharging(5)), Some(Action::Shutdown));
+
+        let mut monitor = Monitor::new(Thresholds {
+            warn: 0,
+            save: 0,
+            shutdown: 0,
+        });
+        assert_eq!(monitor.check(discharging(0)), None);
+    }
+}
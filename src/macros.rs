@@ -0,0 +1,173 @@
+//! Buttons that type a sequence of keys (cheats, quicksave, ...)
+
+use doomgeneric::input::KeyData;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// How long each key of a macro is held and the pause after releasing it
+const KEY_HOLD_DURATION: Duration = Duration::from_millis(60);
+const KEY_GAP_DURATION: Duration = Duration::from_millis(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroStep {
+    Key(u8),
+    Wait(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    /// Parse a definition like `Quicksave: <f6> <wait:300> y`.
+    ///
+    /// Characters are typed as is (whitespace is ignored). Special keys go into angle
+    /// brackets: `<enter>`, `<esc>`, `<space>`, `<backspace>`, `<tab>`, `<f1>`-`<f12>`,
+    /// `<up>`, `<down>`, `<left>`, `<right>` and `<wait:MILLISECONDS>`.
+    pub fn parse(definition: &str) -> Result<Self, String> {
+        let (name, sequence) = definition
+            .split_once(':')
+            .ok_or_else(|| format!("Macro {definition:?} has no \"name:\" prefix"))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("Macro {definition:?} has an empty name"));
+        }
+
+        let mut steps = vec![];
+        let mut chars = sequence.chars();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c != '<' {
+                if !c.is_ascii() {
+                    return Err(format!("Can't type {c:?} in macro {name:?}"));
+                }
+                steps.push(MacroStep::Key(c.to_ascii_lowercase() as u8));
+                continue;
+            }
+
+            let special: String = chars.by_ref().take_while(|c| *c != '>').collect();
+            let special = special.to_ascii_lowercase();
+            steps.push(if let Some(millis) = special.strip_prefix("wait:") {
+                let millis = millis
+                    .parse()
+                    .map_err(|_| format!("Invalid wait <{special}> in macro {name:?}"))?;
+                MacroStep::Wait(Duration::from_millis(millis))
+            } else {
+                MacroStep::Key(
+                    named_key(&special)
+                        .ok_or_else(|| format!("Unknown key <{special}> in macro {name:?}"))?,
+                )
+            });
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            steps,
+        })
+    }
+
+    /// Inverse of [`Macro::parse`]
+    pub fn to_definition(&self) -> String {
+        let mut definition = format!("{}:", self.name);
+        for step in &self.steps {
+            definition.push(' ');
+            match step {
+                MacroStep::Wait(duration) => {
+                    definition.push_str(&format!("<wait:{}>", duration.as_millis()))
+                }
+                MacroStep::Key(key) => match key_name(*key) {
+                    Some(name) => definition.push_str(&format!("<{name}>")),
+                    None => definition.push(*key as char),
+                },
+            }
+        }
+        definition
+    }
+}
+
+// https://github.com/ozkl/doomgeneric/blob/613f870b6fa83ede448a247de5a2571092fa729d/doomgeneric/doomkeys.h
+const NAMED_KEYS: &[(&str, u8)] = &[
+    ("enter", 13),
+    ("esc", 27),
+    ("space", b' '),
+    ("backspace", 0x7f),
+    ("tab", 9),
+    ("up", 0xad),
+    ("down", 0xaf),
+    ("left", 0xac),
+    ("right", 0xae),
+    ("f1", 0x80 + 0x3b),
+    ("f2", 0x80 + 0x3c),
+    ("f3", 0x80 + 0x3d),
+    ("f4", 0x80 + 0x3e),
+    ("f5", 0x80 + 0x3f),
+    ("f6", 0x80 + 0x40),
+    ("f7", 0x80 + 0x41),
+    ("f8", 0x80 + 0x42),
+    ("f9", 0x80 + 0x43),
+    ("f10", 0x80 + 0x44),
+    ("f11", 0x80 + 0x57),
+    ("f12", 0x80 + 0x58),
+];
+
+pub fn named_key(name: &str) -> Option<u8> {
+    NAMED_KEYS
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| *key)
+}
+
+pub fn key_name(key: u8) -> Option<&'static str> {
+    NAMED_KEYS
+        .iter()
+        .find(|(_, named_key)| *named_key == key)
+        .map(|(name, _)| *name)
+}
+
+/// Macros that are always available in the cheats layout
+pub fn builtin_macros() -> Vec<Macro> {
+    [
+        "God mode: iddqd",
+        "All keys: idkfa",
+        "Ammo: idfa",
+        "No clip: idclip",
+        "Reveal map: iddt", // Only while the map is open
+        "Chainsaw: idchoppers",
+        // The game asks for confirmation if a quicksave slot was already picked
+        "Quicksave: <f6> <wait:200> y",
+        "Quickload: <f9> <wait:200> y",
+    ]
+    .iter()
+    .map(|definition| Macro::parse(definition).unwrap())
+    .collect()
+}
+
+/// Type the macro in the background
+pub fn play(macro_: Macro, keydata_tx: Sender<KeyData>) {
+    std::thread::spawn(move || play_blocking(macro_, &keydata_tx));
+}
+
+/// Type the macro and return once all keys are released
+pub fn play_blocking(macro_: Macro, keydata_tx: &Sender<KeyData>) {
+    debug!("Playing macro {:?}", macro_.name);
+    for step in macro_.steps {
+        match step {
+            MacroStep::Wait(duration) => std::thread::sleep(duration),
+            MacroStep::Key(key) => {
+                keydata_tx.send(KeyData { key, pressed: true }).ok();
+                std::thread::sleep(KEY_HOLD_DURATION);
+                keydata_tx
+                    .send(KeyData {
+                        key,
+                        pressed: false,
+                    })
+                    .ok();
+                std::thread::sleep(KEY_GAP_DURATION);
+            }
+        }
+    }
+}
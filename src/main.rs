@@ -6,32 +6,84 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 #[macro_use]
 extern crate log;
 
+use config::CONFIG;
 use doomgeneric::{game, game::DoomGeneric, input::KeyData};
 use libremarkable::cgmath::Point2;
 use libremarkable::framebuffer::core::Framebuffer;
 use libremarkable::framebuffer::{common, PartialRefreshMode};
 use libremarkable::framebuffer::{FramebufferDraw, FramebufferIO, FramebufferRefresh};
 use libremarkable::image::{DynamicImage, RgbImage};
-use libremarkable::input::{ev::EvDevContext, InputDevice, InputEvent};
-use std::io::Cursor;
-use std::sync::atomic::AtomicBool;
+use libremarkable::input::{ev::EvDevContext, GPIOEvent, InputDevice, InputEvent, PhysicalButton};
+use rotation::Rotation;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::{LazyLock, Mutex};
 use std::time::{Duration, Instant};
 
+mod autosave;
+mod battery;
 mod blue_noise_dither;
+mod clock;
+mod config;
+mod doom_hud;
 mod evdev_keyboard;
+mod governor;
+mod headless;
 mod layout;
-
-const SCALE_FACTOR: usize = 2;
+mod low_battery;
+mod macros;
+mod pause;
+mod perf;
+mod preprocess;
+mod recording;
+mod regions;
+mod render_scale;
+mod rotation;
+mod saves;
+mod shutdown;
+mod status_bar;
+mod suspend;
+mod waveform;
+
+/// The press that wakes the device can arrive after resuming and must not suspend it again
+const POWER_BUTTON_DEBOUNCE: Duration = Duration::from_secs(2);
+/// Max time to wait for the game to pause before the process stops or the device sleeps
+const PAUSE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Everything the input thread reacts to
+enum UiInput {
+    Touch(InputEvent),
+    /// Buttons of the device (power button)
+    Gpio(InputEvent),
+    /// Key from a keyboard or other input device
+    Key(KeyData),
+    /// From the battery polling in the render thread
+    LowBattery(low_battery::Action),
+}
 pub static FB: LazyLock<Mutex<Framebuffer>> = LazyLock::new(|| Mutex::new(Framebuffer::default()));
+/// Counts calls to clear(), so the render thread knows when to draw the status bar again
+static SCREEN_CLEARS: AtomicUsize = AtomicUsize::new(0);
 
 struct Game {
     image: std::sync::Arc<std::sync::Mutex<RgbImage>>,
     keydata_receiver: std::sync::mpsc::Receiver<KeyData>,
+    /// Tics the game polled keys for so far. Used to time recorded keys.
+    tic: u64,
+    recorder: Option<recording::Recorder>,
+    replayer: Option<recording::Replayer>,
+    headless: Option<headless::Output>,
+    /// Keys that arrived while the game was held by the auto pause
+    held_keys: std::collections::VecDeque<KeyData>,
+    /// Tic of the last key press, so the game can run a moment after it even when paused
+    last_key_tic: u64,
 }
 
 impl DoomGeneric for Game {
     fn draw_frame(&mut self, screen_buffer: &[u32], xres: usize, yres: usize) {
+        // The frame is complete at this point, so it's safe to stop the engine
+        if shutdown::is_requested() {
+            shutdown::finish();
+        }
+
         let mut rgb_img = libremarkable::image::RgbImage::new(xres as u32, yres as u32);
         assert!(xres * yres == screen_buffer.len());
 
@@ -48,9 +100,42 @@ impl DoomGeneric for Game {
         }
 
         *self.image.lock().unwrap() = rgb_img;
+        perf::frame_produced();
     }
     fn get_key(&mut self) -> Option<KeyData> {
-        self.keydata_receiver.try_recv().ok()
+        let keydata = if let Some(replayer) = &mut self.replayer {
+            // Live input is ignored while replaying
+            while self.keydata_receiver.try_recv().is_ok() {}
+            let keydata = replayer.next_key(self.tic);
+            if replayer.is_finished() {
+                info!("Replay finished. Using live input again.");
+                self.replayer = None;
+            }
+            keydata
+        } else {
+            self.held_keys
+                .pop_front()
+                .or_else(|| self.keydata_receiver.try_recv().ok())
+        };
+
+        if let (Some(recorder), Some(keydata)) = (&mut self.recorder, &keydata) {
+            recorder.record(self.tic, keydata);
+        }
+        if let Some(keydata) = &keydata {
+            if keydata.pressed {
+                self.last_key_tic = self.tic;
+            }
+        } else {
+            // The game polls once per tic until there are no keys left
+            self.tic += 1;
+            if let Some(headless) = &mut self.headless {
+                headless.tic_ended(self.replayer.is_none(), &self.image.lock().unwrap());
+            }
+            if self.replayer.is_none() && self.tic > self.last_key_tic + pause::TICS_AFTER_KEY {
+                self.held_keys.extend(pause::hold(&self.keydata_receiver));
+            }
+        }
+        keydata
     }
     fn set_window_title(&mut self, _title: &str) {
         //self.indow.ctx.window().set_title(title);
@@ -59,9 +144,17 @@ impl DoomGeneric for Game {
 
 fn clear() {
     let _ = FB.lock().unwrap().clear();
+    SCREEN_CLEARS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 }
 
 fn draw_title() {
+    let (rotation, doom_status_bar) = {
+        let config = CONFIG.lock().unwrap();
+        (config.portrait_rotation, config.doom_status_bar)
+    };
+    if doom_status_bar.shows_panel() {
+        return; // The render thread draws the enlarged status bar there
+    }
     let title_text = concat!("DOOMarkable v", env!("CARGO_PKG_VERSION"));
     let subtitle_text = "https://github.com/LinusCDE/doomarkable";
     let title_size = 80;
@@ -81,7 +174,9 @@ fn draw_title() {
         true,
     );
 
-    FB.lock().unwrap().draw_text(
+    rotation::draw_text(
+        &mut FB.lock().unwrap(),
+        rotation,
         Point2 {
             x: (common::DISPLAYWIDTH as u32 - title_rect.width) as f32 / 2.0,
             y: (62 - 20 + title_size) as f32,
@@ -91,7 +186,9 @@ fn draw_title() {
         common::color::BLACK,
         false,
     );
-    FB.lock().unwrap().draw_text(
+    rotation::draw_text(
+        &mut FB.lock().unwrap(),
+        rotation,
         Point2 {
             x: (common::DISPLAYWIDTH as u32 - subtitle_rect.width) as f32 / 2.0,
             y: (62 - 20 + title_size + subtitle_size) as f32,
@@ -103,6 +200,39 @@ fn draw_title() {
     );
 }
 
+/// Big centered text, e.g. while the device sleeps or after exiting
+fn draw_message_screen(title: &str, subtitle: Option<&str>) {
+    let rotation = CONFIG.lock().unwrap().portrait_rotation;
+    clear();
+    let mut y = common::DISPLAYHEIGHT as f32 / 2.0;
+    for (text, size) in [(title, 150f32)]
+        .iter()
+        .chain(subtitle.map(|subtitle| (subtitle, 40f32)).iter())
+    {
+        let text_rect = FB.lock().unwrap().draw_text(
+            Point2 { x: 0f32, y: 0f32 },
+            text,
+            *size,
+            common::color::BLACK,
+            true,
+        );
+        rotation::draw_text(
+            &mut FB.lock().unwrap(),
+            rotation,
+            Point2 {
+                x: (common::DISPLAYWIDTH as u32 - text_rect.width) as f32 / 2.0,
+                y,
+            },
+            text,
+            *size,
+            common::color::BLACK,
+            false,
+        );
+        y += *size;
+    }
+    full_refresh();
+}
+
 fn full_refresh() {
     FB.lock().unwrap().full_refresh(
         common::waveform_mode::WAVEFORM_MODE_GC16,
@@ -119,10 +249,38 @@ fn main() {
     }
     env_logger::init();
 
-    // Ensure .savegame and wad file are always relative to the home directory
-    std::env::set_current_dir("/home/root").unwrap();
+    // Tool for tuning the preprocessing. Doesn't touch the display.
+    if let Some(dir) = std::env::var_os(preprocess::COMPARE_ENV) {
+        let (scale, preprocessing) = {
+            let config = CONFIG.lock().unwrap();
+            (config.render_scale, config.preprocessing())
+        };
+        if let Err(err) = preprocess::compare(dir.as_ref(), scale, &preprocessing) {
+            error!("Failed to compare frames: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Replays a recording without display and input devices
+    if let Some(output) = std::env::var_os(headless::HEADLESS_ENV) {
+        headless::run(output.into());
+        return;
+    }
+
+    suspend::install_signal_handlers();
+    shutdown::install_signal_handlers();
 
-    let mut preparing_text_rect = FB.lock().unwrap().draw_text(
+    // The game keeps its saves in the working directory and finds the wad file through DOOMWADDIR
+    if let Err(err) = saves::enter_saves_dir() {
+        error!("Failed to use a separate saves directory: {err}");
+        std::env::set_current_dir("/home/root").unwrap();
+    }
+
+    let portrait_rotation = CONFIG.lock().unwrap().portrait_rotation;
+    let preparing_text_rect = rotation::draw_text(
+        &mut FB.lock().unwrap(),
+        portrait_rotation,
         Point2 {
             x: 600f32,
             y: (1872 / 2) as f32,
@@ -132,6 +290,7 @@ fn main() {
         common::color::BLACK,
         false,
     );
+    let mut preparing_text_rect = portrait_rotation.to_screen_rect(&preparing_text_rect);
     preparing_text_rect.left -= 50;
     preparing_text_rect.top -= 50;
     preparing_text_rect.width += 50 * 2;
@@ -147,20 +306,10 @@ fn main() {
     );
     clear();
 
-    // The dither_cache was calculated in build/main.rs and
-    // this env is set to the file path containing this cache.
     let start = Instant::now();
-    let dither_cache_compressed = include_bytes!(env!("OUT_DIR_DITHERCACHE_FILE"));
-    let mut dither_cache_raw = Cursor::new(Vec::with_capacity(
-        blue_noise_dither::CachedDither4X::calc_dither_cache_len(
-            game::DOOMGENERIC_RESX as u32 / 2,
-            game::DOOMGENERIC_RESY as u32 / 2,
-        ) * 2,
-    ));
-    zstd::stream::copy_decode(Cursor::new(dither_cache_compressed), &mut dither_cache_raw).unwrap();
-    let dither_cache_raw = dither_cache_raw.into_inner();
-    let mut ditherer = blue_noise_dither::CachedDither4X::new(dither_cache_raw);
+    let mut ditherer = blue_noise_dither::CachedDither::load(CONFIG.lock().unwrap().render_scale);
     info!("Loaded dither cache in {:?}", start.elapsed());
+    let mut resampled_ditherer = blue_noise_dither::ResampledDither::new();
 
     // Create grayscale to native pixel color map
     let mut gray_to_native = [(0u8, 0u8); 256];
@@ -183,29 +332,63 @@ fn main() {
     let image_clone = image.clone();
     let fullscreen = std::sync::Arc::new(AtomicBool::new(false));
     let fullscreen_clone = fullscreen.clone();
+    let (ui_input_tx, ui_input_rx) = std::sync::mpsc::channel::<UiInput>();
+    let low_battery_tx = ui_input_tx.clone();
     std::thread::spawn(move || {
         let mut last_frame_drawn = Instant::now() - Duration::from_millis(1000);
-        let width = game::DOOMGENERIC_RESX as u32 * SCALE_FACTOR as u32;
-        //let height = game::DOOMGENERIC_RESY as u32 * SCALE_FACTOR as u32;
-        let pos = Point2 {
-            x: (common::DISPLAYWIDTH as i32 - width as i32) / 2,
-            //y: (common::DISPLAYHEIGHT as i32 - height as i32) / 2,
-            y: 62 + 140,
-        };
+        // Only the start value. The governor adapts it to the panel.
         let max_fps = match libremarkable::device::CURRENT_DEVICE.model {
             // Will probably not quite hit these anyways
             libremarkable::device::Model::Gen1 => 15,
             // The rM 2 "can" do more, but will result in async frames and more lag. Won't be anymore fluid anyways.
             libremarkable::device::Model::Gen2 => 3,
         };
-        let frame_duration = Duration::from_micros(1000000 / max_fps);
-
-        let battery_indicator_update_interval = Duration::from_secs(30);
-        let mut last_battery_indicator_update = Instant::now() - battery_indicator_update_interval;
-        let mut last_battery_percentage = -99;
+        let mut governor = governor::Governor::new(
+            CONFIG.lock().unwrap().fps_limit,
+            Duration::from_micros(1000000 / max_fps),
+        );
+        // Only for waiting on refreshes, so FB stays usable by the input thread meanwhile
+        let wait_fb = Framebuffer::default();
+
+        let (power_supply_root, battery_thresholds) = {
+            let config = CONFIG.lock().unwrap();
+            (
+                config.power_supply_root.clone(),
+                config.battery_thresholds(),
+            )
+        };
+        let mut status_bar =
+            status_bar::StatusBar::new(Box::new(battery::SysfsBattery::new(power_supply_root)));
+        let mut status_bar_clears = 0;
+        let mut status_bar_rotation = Rotation::None;
+        let mut low_battery_monitor = low_battery::Monitor::new(battery_thresholds);
+        let mut perf = perf::Perf::new();
+        let mut hud_panel = doom_hud::HudPanel::new();
+        let mut frame_capture = preprocess::FrameCapture::from_env();
+        let mut waveform_policy =
+            waveform::WaveformPolicy::new(CONFIG.lock().unwrap().ghost_cleanup());
+        let mut suspended_since = None;
 
         loop {
+            if shutdown::is_requested() {
+                perf.flush_trace();
+                shutdown::render_stopped();
+                break;
+            }
+            if suspend::is_suspended() {
+                suspended_since.get_or_insert_with(Instant::now);
+                std::thread::sleep(governor.frame_interval());
+                continue;
+            }
+            // Everything got cleared while suspended. Redraw and refresh all after this frame.
+            let resumed = suspended_since.is_some();
+            if let Some(since) = suspended_since.take() {
+                status_bar.add_suspended(since.elapsed());
+            }
+
             // Limit fps
+            governor.set_limit(CONFIG.lock().unwrap().fps_limit);
+            let frame_duration = governor.frame_interval();
             let elapsed = last_frame_drawn.elapsed();
             if elapsed < frame_duration {
                 //debug!("Hitting max fps!!!");
@@ -218,135 +401,456 @@ fn main() {
                 continue;
             }
 
-            // Battery indicator in corner
-            if last_battery_indicator_update.elapsed() > battery_indicator_update_interval {
-                let is_fullscreen = fullscreen_clone.load(std::sync::atomic::Ordering::Relaxed);
-                last_battery_indicator_update = Instant::now();
-                let percentage = if is_fullscreen {
-                    -1
-                } else {
-                    libremarkable::battery::percentage().unwrap_or(-1)
-                };
-                if percentage != last_battery_percentage {
-                    last_battery_percentage = percentage;
-                    if !is_fullscreen {
-                        let text = format!("{}%    ", percentage); // Spaces to prevent residual text when text gets narrower
-                        let rect = FB.lock().unwrap().draw_text(
-                            Point2 {
-                                x: 10.0,
-                                y: (common::DISPLAYHEIGHT - 10) as f32,
-                            },
-                            &text,
-                            30f32,
-                            common::color::BLACK,
-                            false,
-                        );
-                        FB.lock().unwrap().partial_refresh(
-                            &rect,
-                            PartialRefreshMode::Async,
-                            common::waveform_mode::WAVEFORM_MODE_GC16_FAST,
-                            common::display_temp::TEMP_USE_MAX,
-                            common::dither_mode::EPDC_FLAG_USE_REMARKABLE_DITHER,
-                            0,
-                            false,
-                        );
-                        debug!("Updated battery indicator");
+            let (
+                portrait_rotation,
+                fullscreen_rotation,
+                render_scale,
+                fullscreen_size,
+                doom_status_bar,
+            ) = {
+                let config = CONFIG.lock().unwrap();
+                (
+                    config.portrait_rotation,
+                    config.fullscreen_rotation,
+                    config.render_scale,
+                    config.fullscreen_scale.target_size(
+                        config.fullscreen_rotation.logical_display_size(),
+                        config.aspect_correction,
+                    ),
+                    config.doom_status_bar,
+                )
+            };
+            let fullscreen = fullscreen_clone.load(std::sync::atomic::Ordering::Relaxed);
+            if render_scale != ditherer.scale() {
+                // The screen got cleared by the settings, so nothing of the old size remains
+                let start = Instant::now();
+                ditherer = blue_noise_dither::CachedDither::load(render_scale);
+                info!(
+                    "Loaded dither cache for {} in {:?}",
+                    render_scale.name(),
+                    start.elapsed()
+                );
+            }
+            let pos = render_scale.portrait_pos();
+
+            if status_bar.poll_battery() {
+                low_battery_monitor.set_thresholds(CONFIG.lock().unwrap().battery_thresholds());
+                let battery = status_bar.battery();
+                if let Some(action) = battery.and_then(|battery| low_battery_monitor.check(battery))
+                {
+                    warn!("Battery at {}%: {action:?}", battery.unwrap().percentage);
+                    if action == low_battery::Action::Warn {
+                        status_bar.show_toast("Battery low! Please charge soon.");
+                    } else {
+                        low_battery_tx.send(UiInput::LowBattery(action)).ok();
                     }
                 }
             }
 
-            let rgb_img = &image.lock().unwrap().clone();
+            // Status bar (hidden in fullscreen)
+            if !fullscreen {
+                let clears = SCREEN_CLEARS.load(std::sync::atomic::Ordering::Relaxed);
+                if clears != status_bar_clears || portrait_rotation != status_bar_rotation {
+                    status_bar_clears = clears;
+                    status_bar_rotation = portrait_rotation;
+                    status_bar.invalidate();
+                    perf.invalidate();
+                    hud_panel.invalidate();
+                }
+                let perf_overlay = CONFIG.lock().unwrap().perf_overlay;
+                let mut fb = FB.lock().unwrap();
+                if !suspend::is_suspended() {
+                    status_bar.draw(&mut fb, portrait_rotation);
+                    if perf_overlay {
+                        perf.draw_overlay(&mut fb, portrait_rotation);
+                    } else {
+                        perf.clear_overlay(&mut fb, portrait_rotation);
+                    }
+                }
+            }
 
+            let mut timing = perf::FrameTiming::default();
             let start = Instant::now();
+            let rgb_img = &image.lock().unwrap().clone();
+
             // Downscale 2x (doomgeneric does a simple upscale anyways, so no data lost)
             // TODO: Remove need for downscaling in doomgeneric-rs
             let rgb_img = RgbImage::from_fn(rgb_img.width() / 2, rgb_img.height() / 2, |x, y| {
                 *rgb_img.get_pixel(x * 2, y * 2)
             });
+            // The enlarged status bar can replace the one inside the game
+            let game_rows = if fullscreen {
+                rgb_img.height()
+            } else {
+                doom_status_bar.game_rows()
+            };
+            let game_frame = libremarkable::image::imageops::crop_imm(
+                &rgb_img,
+                0,
+                0,
+                rgb_img.width(),
+                game_rows,
+            )
+            .to_image();
+            timing.convert = start.elapsed();
+
+            if let Some(frame_capture) = &mut frame_capture {
+                frame_capture.frame(&rgb_img);
+            }
+
+            let start = Instant::now();
+            let (region_dithering, region_outlines, preprocessing) = {
+                let config = CONFIG.lock().unwrap();
+                (
+                    config.region_dithering,
+                    config.region_outlines,
+                    config.preprocess.then(|| config.preprocessing()),
+                )
+            };
+            ditherer.set_preprocessing(preprocessing);
+            resampled_ditherer.set_preprocessing(preprocessing);
+            let regions = if region_dithering || region_outlines {
+                regions::detect(&game_frame)
+            } else {
+                vec![]
+            };
+            let treatments = region_dithering.then(|| regions::treatments(&game_frame, &regions));
+            let frame_size = game_frame.dimensions();
+            let game_img = DynamicImage::ImageRgb8(game_frame);
+            let mut dithered_img = match fullscreen_size {
+                Some((width, height)) if fullscreen => {
+                    resampled_ditherer.dither_image(&game_img, treatments.as_deref(), width, height)
+                }
+                _ => ditherer.dither_image(&game_img, treatments.as_deref()),
+            };
+            if region_outlines {
+                regions::draw_outlines(&mut dithered_img, &regions, frame_size);
+            }
+            timing.dither = start.elapsed();
+            debug!("Dithering took {:?}", timing.dither);
+
+            waveform_policy.set_settings(CONFIG.lock().unwrap().ghost_cleanup());
+            let refresh = waveform_policy.choose(&dithered_img);
 
-            let dithered_img = ditherer.dither_image(&DynamicImage::ImageRgb8(rgb_img));
-            debug!("Dithering took {:?}", start.elapsed());
+            let start = Instant::now();
+            // Don't overwrite the previous frame while the panel still shows it
+            governor.wait_for_previous(&wait_fb);
+            let mut fb = FB.lock().unwrap();
+            if suspend::is_suspended() {
+                continue; // Don't draw over the paused screen
+            }
+            timing.wait = start.elapsed();
 
             let start = Instant::now();
-            let game_rect = if fullscreen_clone.load(std::sync::atomic::Ordering::Relaxed) {
-                draw_image_mono_fullscreen(&mut FB.lock().unwrap(), &dithered_img, &gray_to_native)
+            let game_rect = if fullscreen {
+                // Centered on the landscape display
+                let (display_width, display_height) = fullscreen_rotation.logical_display_size();
+                let fullscreen_pos = Point2 {
+                    x: (display_width as i32 - dithered_img.width() as i32) / 2,
+                    y: (display_height as i32 - dithered_img.height() as i32) / 2,
+                };
+                let game_rect = draw_image_mono_rotated(
+                    &mut fb,
+                    fullscreen_pos,
+                    &dithered_img,
+                    &gray_to_native,
+                    fullscreen_rotation,
+                );
+                // Warnings (like low battery) can't be missed just because the bar is hidden
+                status_bar.draw_toast_over_game(&mut fb, fullscreen_rotation, fullscreen_pos);
+                game_rect
+            } else if portrait_rotation != Rotation::None {
+                draw_image_mono_rotated(
+                    &mut fb,
+                    pos,
+                    &dithered_img,
+                    &gray_to_native,
+                    portrait_rotation,
+                )
             } else {
                 //fb.draw_image(&dithered_img, pos);
-                draw_image_mono(&mut FB.lock().unwrap(), pos, &dithered_img, &gray_to_native)
+                draw_image_mono(&mut fb, pos, &dithered_img, &gray_to_native)
             };
 
-            let waveform = match libremarkable::device::CURRENT_DEVICE.model {
-                libremarkable::device::Model::Gen1 => common::waveform_mode::WAVEFORM_MODE_GLR16,
-                libremarkable::device::Model::Gen2 => common::waveform_mode::WAVEFORM_MODE_DU,
-            };
-            FB.lock().unwrap().partial_refresh(
+            timing.blit = start.elapsed();
+
+            let start = Instant::now();
+            let marker = fb.partial_refresh(
                 &game_rect,
                 PartialRefreshMode::Async,
-                //common::waveform_mode::WAVEFORM_MODE_DU,
-                //common::waveform_mode::WAVEFORM_MODE_GLR16,
-                waveform,
+                refresh.waveform,
                 common::display_temp::TEMP_USE_REMARKABLE_DRAW,
                 common::dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
                 0,
-                false,
+                refresh.full,
             );
+            timing.refresh = start.elapsed();
+            governor.submitted(marker);
 
-            debug!("Drawing took {:?}", start.elapsed());
+            if !fullscreen && doom_status_bar.shows_panel() {
+                hud_panel.draw(&mut fb, &rgb_img, &gray_to_native, portrait_rotation);
+            }
+
+            drop(fb);
+            if resumed {
+                full_refresh();
+            }
+
+            debug!(
+                "Drawing took {:?}",
+                timing.wait + timing.blit + timing.refresh
+            );
+            status_bar.frame_drawn();
+            perf.record(timing, fullscreen);
             last_frame_drawn = Instant::now();
         }
     });
 
     let (keydata_tx, keydata_rx) = std::sync::mpsc::channel::<KeyData>();
-    evdev_keyboard::init(keydata_tx.clone());
+
+    // Keys from devices go through the layouts, which decide if the game gets them
+    let (device_key_tx, device_key_rx) = std::sync::mpsc::channel::<KeyData>();
+    evdev_keyboard::init(device_key_tx);
+    evdev_keyboard::apply_config(false);
+    forward(device_key_rx, ui_input_tx.clone(), UiInput::Key);
 
     std::thread::spawn(move || {
-        let mut layout_manager = layout::LayoutManager::new(&mut FB.lock().unwrap());
-
-        let (input_tx, input_rx) = std::sync::mpsc::channel::<InputEvent>();
-        EvDevContext::new(InputDevice::Multitouch, input_tx).start();
-        let mut fullscreen_since: Option<Instant> = None;
-
-        for event in input_rx {
-            if let Some(fullscreen_since_time) = fullscreen_since {
-                // Any touch 500ms after fullscreen entered => exit fullscreen
-                if fullscreen_since_time.elapsed() > Duration::from_millis(500) {
-                    // Exit out of fullscreen (portrait game, bring back layout)
-                    info!("Exiting fullscreen mode...");
-                    fullscreen.store(false, std::sync::atomic::Ordering::Relaxed);
-                    fullscreen_since = None;
-                    clear();
-                    draw_title();
-                    layout_manager
-                        .switch_layout(layout::LayoutId::Controls, &mut FB.lock().unwrap());
+        let mut layout_manager =
+            layout::LayoutManager::new(&mut FB.lock().unwrap(), keydata_tx.clone());
+
+        let (touch_tx, touch_rx) = std::sync::mpsc::channel::<InputEvent>();
+        EvDevContext::new(InputDevice::Multitouch, touch_tx).start();
+        forward(touch_rx, ui_input_tx.clone(), UiInput::Touch);
+        let (gpio_tx, gpio_rx) = std::sync::mpsc::channel::<InputEvent>();
+        EvDevContext::new(InputDevice::GPIO, gpio_tx).start();
+        forward(gpio_rx, ui_input_tx, UiInput::Gpio);
+
+        let suspend_ui = |layout_manager: &mut layout::LayoutManager, subtitle: Option<&str>| {
+            layout_manager.suspend();
+            // The game may still run for a moment after the last key
+            if !pause::wait_until_held(PAUSE_TIMEOUT) {
+                warn!("The game didn't pause in time");
+            }
+            evdev_keyboard::release_grabs();
+            suspend::SUSPENDED.store(true, std::sync::atomic::Ordering::Relaxed);
+            draw_message_screen("Paused", subtitle);
+        };
+        let resume_ui = |layout_manager: &mut layout::LayoutManager| {
+            let is_fullscreen = fullscreen.load(std::sync::atomic::Ordering::Relaxed);
+            clear();
+            if !is_fullscreen {
+                draw_title();
+            }
+            layout_manager.resume(&mut FB.lock().unwrap());
+            evdev_keyboard::apply_config(is_fullscreen);
+            // The render thread draws the game again and does the full refresh
+            suspend::SUSPENDED.store(false, std::sync::atomic::Ordering::Relaxed);
+        };
+        let mut last_resume = Instant::now() - POWER_BUTTON_DEBOUNCE;
+        // Autosaving before exiting. Input is ignored meanwhile.
+        let mut exiting = false;
+
+        loop {
+            if exiting {
+                if shutdown::is_requested() {
+                    info!("Input handling stopped for shutdown");
+                    break;
+                }
+                ui_input_rx.recv_timeout(layout::TICK_INTERVAL).ok();
+                continue;
+            }
+            let mut outcomes = match ui_input_rx.recv_timeout(layout::TICK_INTERVAL) {
+                Ok(UiInput::Touch(event)) => {
+                    layout_manager.current_layout_mut().handle_input(event)
+                }
+                Ok(UiInput::Gpio(InputEvent::GPIO {
+                    event:
+                        GPIOEvent::Press {
+                            button: PhysicalButton::POWER,
+                        },
+                })) => {
+                    if last_resume.elapsed() > POWER_BUTTON_DEBOUNCE {
+                        info!("Power button pressed. Going to sleep...");
+                        suspend_ui(
+                            &mut layout_manager,
+                            Some("Press the power button to continue"),
+                        );
+                        if let Err(err) = suspend::sleep_device() {
+                            error!("Failed to suspend the device: {err}");
+                        }
+                        info!("Woke up");
+                        resume_ui(&mut layout_manager);
+                        last_resume = Instant::now();
+                    }
+                    vec![]
+                }
+                Ok(UiInput::Gpio(_)) => vec![],
+                Ok(UiInput::Key(keydata)) => layout_manager.handle_key(keydata),
+                Ok(UiInput::LowBattery(low_battery::Action::PauseAndSave)) => {
+                    let mut outcomes = vec![];
+                    if fullscreen.load(std::sync::atomic::Ordering::Relaxed) {
+                        outcomes.push(layout::InputOutcome::ExitFullscreen);
+                    }
+                    outcomes.push(layout::InputOutcome::SwitchLayout(
+                        layout::LayoutId::LowBattery,
+                    ));
+                    if CONFIG.lock().unwrap().autosave {
+                        outcomes.push(layout::InputOutcome::Autosave);
+                    }
+                    outcomes
+                }
+                Ok(UiInput::LowBattery(low_battery::Action::Shutdown)) => {
+                    vec![layout::InputOutcome::Exit]
+                }
+                Ok(UiInput::LowBattery(low_battery::Action::Warn)) => vec![],
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => vec![],
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            match suspend::take_request() {
+                Some(suspend::Request::Stop) => {
+                    info!("Stopping...");
+                    suspend_ui(&mut layout_manager, None);
+                    suspend::stop_process();
+                    info!("Continued");
+                    resume_ui(&mut layout_manager);
+                }
+                Some(suspend::Request::Continued) => {
+                    // Got stopped without notice. Touches and keys might have been missed.
+                    info!("Continued after being stopped");
+                    layout_manager.suspend();
+                    resume_ui(&mut layout_manager);
+                    // The render thread didn't see it suspended, so it won't refresh everything
                     full_refresh();
                 }
-                continue; // No layout handling while in fullscreen
+                None => {}
+            }
+            // Long presses, repeating keys, etc.
+            outcomes.extend(layout_manager.current_layout_mut().tick());
+            if evdev_keyboard::take_devices_changed() {
+                layout_manager.reload_devices(&mut FB.lock().unwrap());
             }
 
-            for outcome in layout_manager.current_layout_mut().handle_input(event) {
+            for outcome in outcomes {
                 match outcome {
                     layout::InputOutcome::KeyData(keydata) => {
                         keydata_tx.send(keydata).ok();
                     }
+                    layout::InputOutcome::PlayMacro(macro_) => {
+                        macros::play(macro_, keydata_tx.clone());
+                    }
                     layout::InputOutcome::SwitchLayout(new_layout_id) => {
                         layout_manager.switch_layout(new_layout_id, &mut FB.lock().unwrap())
                     }
                     layout::InputOutcome::EnterFullscreen => {
-                        // Switch to fullscreen (landscape game, no layout rendering)
+                        // Switch to fullscreen (landscape game, controls on the sides)
                         info!("Entering fullscreen mode...");
-                        fullscreen_since = Some(Instant::now());
                         fullscreen.store(true, std::sync::atomic::Ordering::Relaxed);
+                        evdev_keyboard::apply_config(true);
+                        clear();
+                        layout_manager
+                            .switch_layout(layout::LayoutId::Fullscreen, &mut FB.lock().unwrap());
+                        full_refresh();
+                    }
+                    layout::InputOutcome::Autosave => {
+                        // In the background, so the layouts stay usable meanwhile
+                        let keydata_tx = keydata_tx.clone();
+                        std::thread::spawn(move || autosave::save(&keydata_tx));
+                    }
+                    layout::InputOutcome::SavesChanged => {
+                        layout_manager.reload_saves(&mut FB.lock().unwrap())
+                    }
+                    layout::InputOutcome::ConfirmSavesChange(change) => {
+                        layout_manager.confirm_saves_change(change, &mut FB.lock().unwrap())
+                    }
+                    layout::InputOutcome::ChangeKeyboard(change) => {
+                        layout_manager.change_keyboard(change, &mut FB.lock().unwrap())
+                    }
+                    layout::InputOutcome::SettingsChanged => {
+                        // Before reloading, so the device list shows the new state
+                        evdev_keyboard::apply_config(
+                            fullscreen.load(std::sync::atomic::Ordering::Relaxed),
+                        );
+                        // Title, layouts and game might have moved (e.g. rotation changed)
                         clear();
+                        draw_title();
+                        layout_manager.reload(&mut FB.lock().unwrap());
+                        full_refresh();
+                    }
+                    layout::InputOutcome::Exit => {
+                        if CONFIG.lock().unwrap().autosave {
+                            info!("Autosaving before exiting...");
+                            exiting = true;
+                            suspend::SUSPENDED.store(true, std::sync::atomic::Ordering::Relaxed);
+                            draw_message_screen("Saving...", None);
+                            // In the background, so the input thread isn't blocked meanwhile
+                            let keydata_tx = keydata_tx.clone();
+                            std::thread::spawn(move || {
+                                autosave::save(&keydata_tx);
+                                // The game thread finishes the shutdown after its current frame
+                                shutdown::request();
+                            });
+                        } else {
+                            shutdown::request();
+                        }
+                    }
+                    layout::InputOutcome::ExitFullscreen => {
+                        // Exit out of fullscreen (portrait game, bring back layout)
+                        info!("Exiting fullscreen mode...");
+                        fullscreen.store(false, std::sync::atomic::Ordering::Relaxed);
+                        evdev_keyboard::apply_config(false);
+                        clear();
+                        draw_title();
+                        layout_manager
+                            .switch_layout(layout::LayoutId::Controls, &mut FB.lock().unwrap());
                         full_refresh();
                     }
                 }
             }
+            if shutdown::is_requested() {
+                info!("Input handling stopped for shutdown");
+                break;
+            }
+            layout_manager
+                .current_layout_mut()
+                .update_pressed_buttons(&mut FB.lock().unwrap());
+        }
+    });
+
+    let recorder =
+        std::env::var_os(recording::RECORD_ENV).and_then(|path| match recording::Recorder::create(
+            &path,
+        ) {
+            Ok(recorder) => {
+                info!("Recording input to {path:?}");
+                Some(recorder)
+            }
+            Err(err) => {
+                error!("Failed to create input recording {path:?}: {err}");
+                None
+            }
+        });
+    let replayer = std::env::var_os(recording::REPLAY_ENV).and_then(|path| {
+        match recording::Replayer::load(&path) {
+            Ok(replayer) => {
+                info!("Replaying input from {path:?}");
+                Some(replayer)
+            }
+            Err(err) => {
+                error!("Failed to load input recording {path:?}: {err}");
+                None
+            }
         }
     });
 
     game::init(Game {
         image: image_clone,
         keydata_receiver: keydata_rx,
+        tic: 0,
+        recorder,
+        replayer,
+        headless: None,
+        held_keys: Default::default(),
+        last_key_tic: 0,
     });
     // TODO: Doom hogs the entire cpu when failed to start (no wad file).
     // Need to figure out how to trigger on error.
@@ -379,35 +883,46 @@ fn draw_image_mono(
     rect
 }
 
-fn draw_image_mono_fullscreen(
+/// Like draw_image_mono() but rotated. The pos is in the logical coordinates of the rotation.
+fn draw_image_mono_rotated(
     fb: &mut Framebuffer,
+    pos: Point2<i32>,
     img: &libremarkable::image::GrayImage,
     gray_to_native: &[(u8, u8); 256],
+    rotation: Rotation,
 ) -> common::mxcfb_rect {
-    let (portrait_width, portrait_height) = (img.width() as usize, img.height() as usize);
-    let (landscape_width, landscape_height) = (portrait_height, portrait_width);
+    let (width, height) = (img.width(), img.height());
+    let rect = rotation.to_screen_rect(&common::mxcfb_rect {
+        top: pos.y as u32,
+        left: pos.x as u32,
+        width,
+        height,
+    });
 
-    let mut fb_raw_data: Vec<u8> = vec![0u8; portrait_width * 2 * portrait_height];
+    let mut fb_raw_data: Vec<u8> = vec![0u8; width as usize * 2 * height as usize];
     for (x, y, pixel_value) in img.enumerate_pixels() {
-        // Rotate by 90 degrees
-        let new_x = portrait_height - (y as usize + 1);
-        let new_y = x as usize;
+        let (new_x, new_y) = rotation.map_point(x, y, width, height);
 
         // Put new x and y values into linear fb_raw_data
-        let index = (new_y * landscape_width + new_x) * 2;
+        let index = (new_y as usize * rect.width as usize + new_x as usize) * 2;
         fb_raw_data[index] = gray_to_native[pixel_value.0[0] as usize].0;
         fb_raw_data[index + 1] = gray_to_native[pixel_value.0[0] as usize].1;
     }
-    let pos = Point2 {
-        x: (common::DISPLAYWIDTH as i32 - landscape_width as i32) / 2,
-        y: (common::DISPLAYHEIGHT as i32 - landscape_height as i32) / 2,
-    };
-    let rect = common::mxcfb_rect {
-        top: pos.y as u32,
-        left: pos.x as u32,
-        width: landscape_width as u32,
-        height: landscape_height as u32,
-    };
     fb.restore_region(rect, &fb_raw_data).unwrap();
     rect
 }
+
+/// Pass everything from one channel into another in the background
+fn forward<T: Send + 'static, U: Send + 'static>(
+    rx: std::sync::mpsc::Receiver<T>,
+    tx: std::sync::mpsc::Sender<U>,
+    map: fn(T) -> U,
+) {
+    std::thread::spawn(move || {
+        for item in rx {
+            if tx.send(map(item)).is_err() {
+                break;
+            }
+        }
+    });
+}
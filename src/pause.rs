@@ -0,0 +1,106 @@
+//! Auto pause. The game thread gets held between two tics instead of sending doom's pause
+//! key, which toggles and does nothing outside of levels, so it got out of sync whenever
+//! the player paused by hand or a menu of the game was open.
+//! Key presses still reach the game while it's held, and it keeps running for a moment
+//! after each of them, so macros (like the autosave) work while paused.
+//!
+//! Doom times its tics with the wall clock, but doesn't catch up on the time it was held:
+//! In single player it builds at most 3 tics ahead of the ones it ran (`BuildNewTic` in
+//! d_loop.c), and only runs built tics (`TryRunTics`). So after resuming, at most 3 tics
+//! run at once, like after any other stall of the process (e.g. SIGSTOP).
+use crate::shutdown;
+use doomgeneric::input::KeyData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long the game runs after a key press while paused (doom runs at 35 tics per second)
+pub const TICS_AFTER_KEY: u64 = 35;
+/// How often a held game checks whether it got resumed or should exit
+const HOLD_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static HELD: AtomicBool = AtomicBool::new(false);
+
+/// Set by the layouts. Takes effect at the end of the current tic.
+pub fn set(paused: bool) {
+    REQUESTED.store(paused, Ordering::Relaxed);
+}
+
+/// Called by the game thread at the end of a tic. Waits while paused, until a key press
+/// arrives or the exit got requested. Returns the keys that arrived meanwhile, so the
+/// game gets them next. Releases (like those sent when switching layouts) are only
+/// collected, since they don't need the game to run.
+pub fn hold(keydata_receiver: &Receiver<KeyData>) -> Vec<KeyData> {
+    let mut keys = vec![];
+    while REQUESTED.load(Ordering::Relaxed) && !shutdown::is_requested() {
+        HELD.store(true, Ordering::Relaxed);
+        match keydata_receiver.recv_timeout(HOLD_CHECK_INTERVAL) {
+            Ok(keydata) => {
+                let pressed = keydata.pressed;
+                keys.push(keydata);
+                if pressed {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    HELD.store(false, Ordering::Relaxed);
+    keys
+}
+
+/// Wait until the game is held between two tics after pausing, so the process can be
+/// stopped without interrupting a frame. Gives up after the timeout.
+pub fn wait_until_held(timeout: Duration) -> bool {
+    let start = Instant::now();
+    while !HELD.load(Ordering::Relaxed) {
+        if start.elapsed() > timeout {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: u8, pressed: bool) -> KeyData {
+        KeyData { key, pressed }
+    }
+
+    fn keys(keys: &[KeyData]) -> Vec<(u8, bool)> {
+        keys.iter()
+            .map(|keydata| (keydata.key, keydata.pressed))
+            .collect()
+    }
+
+    // One test, since the pause state is global
+    #[test]
+    fn only_presses_wake_the_game() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        set(false);
+        assert!(hold(&rx).is_empty());
+
+        set(true);
+        tx.send(key(1, false)).unwrap();
+        tx.send(key(2, false)).unwrap();
+        tx.send(key(3, true)).unwrap();
+        tx.send(key(4, true)).unwrap();
+        assert_eq!(keys(&hold(&rx)), vec![(1, false), (2, false), (3, true)]);
+
+        assert_eq!(keys(&hold(&rx)), vec![(4, true)]);
+
+        // Releases are kept until resuming
+        tx.send(key(5, false)).unwrap();
+        let resume = std::thread::spawn(|| {
+            assert!(wait_until_held(Duration::from_secs(5)));
+            set(false);
+        });
+        assert_eq!(keys(&hold(&rx)), vec![(5, false)]);
+        resume.join().unwrap();
+    }
+}
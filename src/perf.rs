@@ -0,0 +1,251 @@
+//! Timing of the render loop. Shown as an overlay below the game and optionally
+//! written to a CSV file with one row per drawn frame.
+
+use crate::rotation::{self, Rotation};
+use libremarkable::cgmath::{Point2, Vector2};
+use libremarkable::framebuffer::core::Framebuffer;
+use libremarkable::framebuffer::{common, PartialRefreshMode};
+use libremarkable::framebuffer::{FramebufferDraw, FramebufferRefresh};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Env var with a file path to write the CSV trace to
+pub const TRACE_ENV: &str = "DOOMARKABLE_FRAME_TRACE";
+
+const CSV_HEADER: &str =
+    "frame,millis,produced,dropped,convert_us,dither_us,wait_us,blit_us,refresh_us,fullscreen";
+/// The overlay shows averages over this long
+const OVERLAY_INTERVAL: Duration = Duration::from_secs(1);
+/// Logical (portrait) area between the game and the layouts
+const OVERLAY_RECT: common::mxcfb_rect = common::mxcfb_rect {
+    left: 62,
+    top: 1010,
+    width: 1404 - 62 * 2,
+    height: 50,
+};
+const OVERLAY_TEXT_SIZE: f32 = 30.0;
+
+/// Frames the game produced (calls to `draw_frame`)
+static FRAMES_PRODUCED: AtomicU64 = AtomicU64::new(0);
+
+pub fn frame_produced() {
+    FRAMES_PRODUCED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How long each step of drawing a frame took
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameTiming {
+    /// Downscaling the frame of the game
+    pub convert: Duration,
+    pub dither: Duration,
+    /// Waiting for the refresh of the previous frame to complete
+    pub wait: Duration,
+    /// Writing into the framebuffer
+    pub blit: Duration,
+    /// Submitting the refresh (not waiting for it)
+    pub refresh: Duration,
+}
+
+impl std::ops::AddAssign for FrameTiming {
+    fn add_assign(&mut self, other: Self) {
+        self.convert += other.convert;
+        self.dither += other.dither;
+        self.wait += other.wait;
+        self.blit += other.blit;
+        self.refresh += other.refresh;
+    }
+}
+
+pub struct Perf {
+    start: Instant,
+    frames_drawn: u64,
+    last_produced: u64,
+    trace: Option<BufWriter<File>>,
+    window_start: Instant,
+    window_frames: u32,
+    window_timing: FrameTiming,
+    window_produced: u64,
+    window_dropped: u64,
+    overlay_text: String,
+    /// Text currently on screen (None if it needs to be drawn)
+    shown: Option<String>,
+}
+
+impl Perf {
+    pub fn new() -> Self {
+        let trace = std::env::var_os(TRACE_ENV).and_then(|path| match create_trace(&path) {
+            Ok(trace) => {
+                info!("Writing frame trace to {path:?}");
+                Some(trace)
+            }
+            Err(err) => {
+                error!("Failed to create frame trace {path:?}: {err}");
+                None
+            }
+        });
+        Self {
+            start: Instant::now(),
+            frames_drawn: 0,
+            last_produced: FRAMES_PRODUCED.load(Ordering::Relaxed),
+            trace,
+            window_start: Instant::now(),
+            window_frames: 0,
+            window_timing: FrameTiming::default(),
+            window_produced: 0,
+            window_dropped: 0,
+            overlay_text: "Measuring...".to_owned(),
+            shown: None,
+        }
+    }
+
+    pub fn record(&mut self, timing: FrameTiming, fullscreen: bool) {
+        // Frames produced since the last drawn one, that never got on screen
+        let produced = FRAMES_PRODUCED.load(Ordering::Relaxed);
+        let new_frames = produced - self.last_produced;
+        let dropped = new_frames.saturating_sub(1);
+        self.last_produced = produced;
+        self.frames_drawn += 1;
+
+        if let Some(trace) = &mut self.trace {
+            let result = writeln!(
+                trace,
+                "{},{},{},{},{},{},{},{},{},{}",
+                self.frames_drawn,
+                self.start.elapsed().as_millis(),
+                produced,
+                dropped,
+                timing.convert.as_micros(),
+                timing.dither.as_micros(),
+                timing.wait.as_micros(),
+                timing.blit.as_micros(),
+                timing.refresh.as_micros(),
+                fullscreen as u8
+            );
+            if let Err(err) = result {
+                error!("Failed to write frame trace: {err}. Stopping it.");
+                self.trace = None;
+            }
+        }
+
+        self.window_frames += 1;
+        self.window_timing += timing;
+        self.window_produced += new_frames;
+        self.window_dropped += dropped;
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= OVERLAY_INTERVAL {
+            let frames = self.window_frames;
+            let average = |duration: Duration| (duration / frames).as_millis();
+            self.overlay_text = format!(
+                "{:.1} FPS  convert {} ms  dither {} ms  wait {} ms  blit {} ms  refresh {} ms  dropped {}/{}",
+                frames as f32 / elapsed.as_secs_f32(),
+                average(self.window_timing.convert),
+                average(self.window_timing.dither),
+                average(self.window_timing.wait),
+                average(self.window_timing.blit),
+                average(self.window_timing.refresh),
+                self.window_dropped,
+                self.window_produced,
+            );
+            // So the file is usable while still running and nothing much gets lost on a kill
+            self.flush_trace();
+            self.window_start = Instant::now();
+            self.window_frames = 0;
+            self.window_timing = FrameTiming::default();
+            self.window_produced = 0;
+            self.window_dropped = 0;
+        }
+    }
+
+    /// Write out the buffered rows of the trace
+    pub fn flush_trace(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            if let Err(err) = trace.flush() {
+                error!("Failed to write frame trace: {err}. Stopping it.");
+                self.trace = None;
+            }
+        }
+    }
+
+    /// The overlay has to be drawn again (e.g. after the screen got cleared)
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// Redraw and refresh the overlay if its text changed
+    pub fn draw_overlay(&mut self, fb: &mut Framebuffer, rotation: Rotation) {
+        if self.shown.as_ref() == Some(&self.overlay_text) {
+            return;
+        }
+        let screen_rect = rotation.to_screen_rect(&OVERLAY_RECT);
+        fb.fill_rect(
+            Point2 {
+                x: screen_rect.left as i32,
+                y: screen_rect.top as i32,
+            },
+            Vector2 {
+                x: screen_rect.width,
+                y: screen_rect.height,
+            },
+            common::color::WHITE,
+        );
+        rotation::draw_text(
+            fb,
+            rotation,
+            Point2 {
+                x: OVERLAY_RECT.left as f32,
+                y: (OVERLAY_RECT.top + OVERLAY_RECT.height - 12) as f32,
+            },
+            &self.overlay_text,
+            OVERLAY_TEXT_SIZE,
+            common::color::BLACK,
+            false,
+        );
+        fb.partial_refresh(
+            &screen_rect,
+            PartialRefreshMode::Async,
+            common::waveform_mode::WAVEFORM_MODE_GC16_FAST,
+            common::display_temp::TEMP_USE_MAX,
+            common::dither_mode::EPDC_FLAG_USE_REMARKABLE_DITHER,
+            0,
+            false,
+        );
+        self.shown = Some(self.overlay_text.clone());
+    }
+
+    /// Remove the overlay from the screen after it got turned off
+    pub fn clear_overlay(&mut self, fb: &mut Framebuffer, rotation: Rotation) {
+        if self.shown.take().is_none() {
+            return;
+        }
+        let screen_rect = rotation.to_screen_rect(&OVERLAY_RECT);
+        fb.fill_rect(
+            Point2 {
+                x: screen_rect.left as i32,
+                y: screen_rect.top as i32,
+            },
+            Vector2 {
+                x: screen_rect.width,
+                y: screen_rect.height,
+            },
+            common::color::WHITE,
+        );
+        fb.partial_refresh(
+            &screen_rect,
+            PartialRefreshMode::Async,
+            common::waveform_mode::WAVEFORM_MODE_GC16_FAST,
+            common::display_temp::TEMP_USE_MAX,
+            common::dither_mode::EPDC_FLAG_USE_REMARKABLE_DITHER,
+            0,
+            false,
+        );
+    }
+}
+
+fn create_trace(path: impl AsRef<Path>) -> std::io::Result<BufWriter<File>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{CSV_HEADER}")?;
+    Ok(writer)
+}
@@ -0,0 +1,223 @@
+//! Optional processing of the grayscale frame before dithering. Dark corridors otherwise
+//! dither into nearly uniform black. Tuned for the 320x200 frame:
+//! - Unsharp mask with a 3x3 blur, so edges survive the dithering
+//! - Local histogram equalisation (CLAHE-style) on a grid of 40x40 tiles
+//! - Darkness floor, so even black areas keep a few dots
+//!
+//! Frames can be captured on the device and compared with and without it, see
+//! [`CAPTURE_ENV`] and [`COMPARE_ENV`].
+
+use crate::blue_noise_dither::CachedDither;
+use crate::render_scale::{RenderScale, SOURCE_HEIGHT, SOURCE_WIDTH};
+use libremarkable::image::{DynamicImage, GrayImage, RgbImage};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Env var with a directory to save frames (320x200 PNGs) to while playing
+pub const CAPTURE_ENV: &str = "DOOMARKABLE_CAPTURE";
+/// Env var with a directory of captured frames. Writes before/after PNGs
+/// into `compare/` of it and exits instead of starting the game.
+pub const COMPARE_ENV: &str = "DOOMARKABLE_COMPARE";
+
+const CAPTURE_INTERVAL: Duration = Duration::from_secs(2);
+const TILES_X: u32 = 8;
+const TILES_Y: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// Strength of the unsharp mask in percent (0 disables it)
+    pub sharpen_percentage: u8,
+    /// Clip limit of the local equalisation as a multiple of an even histogram (0 disables it)
+    pub local_contrast_limit: f32,
+    /// Darkest gray value after processing
+    pub darkness_floor: u8,
+}
+
+pub fn apply(img: &mut GrayImage, settings: &Settings) {
+    if settings.local_contrast_limit > 0.0 {
+        equalize_locally(img, settings.local_contrast_limit);
+    }
+    if settings.sharpen_percentage > 0 {
+        sharpen(img, settings.sharpen_percentage);
+    }
+    if settings.darkness_floor > 0 {
+        let floor = settings.darkness_floor as u32;
+        for pixel in img.iter_mut() {
+            *pixel = (floor + *pixel as u32 * (255 - floor) / 255) as u8;
+        }
+    }
+}
+
+fn sharpen(img: &mut GrayImage, percentage: u8) {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let original = img.as_raw().clone();
+    // Separable 3x3 box blur (sums of 3, clamped at the borders)
+    let mut horizontal = vec![0u16; width * height];
+    for y in 0..height {
+        let line = &original[y * width..][..width];
+        for x in 0..width {
+            horizontal[y * width + x] = line[x.saturating_sub(1)] as u16
+                + line[x] as u16
+                + line[(x + 1).min(width - 1)] as u16;
+        }
+    }
+    let amount = percentage as i32;
+    let pixels: &mut [u8] = img;
+    for y in 0..height {
+        let above = y.saturating_sub(1) * width;
+        let below = (y + 1).min(height - 1) * width;
+        for x in 0..width {
+            let i = y * width + x;
+            let blurred =
+                (horizontal[above + x] + horizontal[i] + horizontal[below + x]) as i32 / 9;
+            let value = original[i] as i32;
+            pixels[i] = (value + (value - blurred) * amount / 100).clamp(0, 255) as u8;
+        }
+    }
+}
+
+fn equalize_locally(img: &mut GrayImage, clip_limit: f32) {
+    let (width, height) = img.dimensions();
+    let tile_width = width.div_ceil(TILES_X);
+    let tile_height = height.div_ceil(TILES_Y);
+
+    // Mapping of every gray value for each tile
+    let mut luts = vec![[0u8; 256]; (TILES_X * TILES_Y) as usize];
+    for tile_y in 0..TILES_Y {
+        for tile_x in 0..TILES_X {
+            let (left, top) = (tile_x * tile_width, tile_y * tile_height);
+            let (right, bottom) = (
+                (left + tile_width).min(width),
+                (top + tile_height).min(height),
+            );
+            let pixels = (right.saturating_sub(left) * bottom.saturating_sub(top)).max(1);
+
+            let mut histogram = [0u32; 256];
+            for y in top..bottom {
+                for x in left..right {
+                    histogram[img.get_pixel(x, y).0[0] as usize] += 1;
+                }
+            }
+            // Clipping limits how much the contrast gets boosted (and noise with it)
+            let limit = ((clip_limit * pixels as f32 / 256.0) as u32).max(1);
+            let mut excess = 0;
+            for count in histogram.iter_mut() {
+                if *count > limit {
+                    excess += *count - limit;
+                    *count = limit;
+                }
+            }
+            let redistributed = excess / 256;
+            let mut sum = 0;
+            let lut = &mut luts[(tile_y * TILES_X + tile_x) as usize];
+            for (value, count) in histogram.iter().enumerate() {
+                sum += count + redistributed;
+                lut[value] = (sum.min(pixels) * 255 / pixels) as u8;
+            }
+        }
+    }
+
+    // Blend the mappings of the four nearest tile centers, so no tile edges show up
+    let tile_position = |pos: u32, tile_size: u32, tiles: u32| {
+        let pos = (pos as f32 + 0.5) / tile_size as f32 - 0.5;
+        let first = pos.floor().max(0.0) as u32;
+        let first = first.min(tiles - 1);
+        let second = (first + 1).min(tiles - 1);
+        let weight = (pos - first as f32).clamp(0.0, 1.0);
+        (first, second, weight)
+    };
+    for y in 0..height {
+        let (y0, y1, wy) = tile_position(y, tile_height, TILES_Y);
+        for x in 0..width {
+            let (x0, x1, wx) = tile_position(x, tile_width, TILES_X);
+            let value = img.get_pixel(x, y).0[0] as usize;
+            let lut =
+                |tile_x: u32, tile_y: u32| luts[(tile_y * TILES_X + tile_x) as usize][value] as f32;
+            let top = lut(x0, y0) * (1.0 - wx) + lut(x1, y0) * wx;
+            let bottom = lut(x0, y1) * (1.0 - wx) + lut(x1, y1) * wx;
+            img.put_pixel(
+                x,
+                y,
+                libremarkable::image::Luma([(top * (1.0 - wy) + bottom * wy) as u8]),
+            );
+        }
+    }
+}
+
+/// Saves a frame every few seconds
+pub struct FrameCapture {
+    dir: PathBuf,
+    last_capture: Option<Instant>,
+    count: usize,
+}
+
+impl FrameCapture {
+    /// Only if [`CAPTURE_ENV`] is set
+    pub fn from_env() -> Option<Self> {
+        let dir = PathBuf::from(std::env::var_os(CAPTURE_ENV)?);
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            error!("Failed to create the capture directory {dir:?}: {err}");
+            return None;
+        }
+        info!("Capturing frames to {dir:?}");
+        Some(Self {
+            dir,
+            last_capture: None,
+            count: 0,
+        })
+    }
+
+    /// Takes the downscaled frame
+    pub fn frame(&mut self, frame: &RgbImage) {
+        if self
+            .last_capture
+            .is_some_and(|last| last.elapsed() < CAPTURE_INTERVAL)
+        {
+            return;
+        }
+        self.last_capture = Some(Instant::now());
+        let path = self.dir.join(format!("frame-{:05}.png", self.count));
+        self.count += 1;
+        if let Err(err) = frame.save(&path) {
+            error!("Failed to capture frame to {path:?}: {err}");
+        }
+    }
+}
+
+/// Dithers every PNG in the directory without and with the preprocessing
+pub fn compare(dir: &Path, scale: RenderScale, settings: &Settings) -> std::io::Result<()> {
+    let out_dir = dir.join("compare");
+    std::fs::create_dir_all(&out_dir)?;
+    let mut ditherer = CachedDither::load(scale);
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let frame = match libremarkable::image::open(&path) {
+            Ok(frame) if frame.width() == SOURCE_WIDTH && frame.height() == SOURCE_HEIGHT => {
+                DynamicImage::ImageRgb8(frame.to_rgb8())
+            }
+            Ok(_) => {
+                warn!("Skipping {path:?}: Not a captured frame ({SOURCE_WIDTH}x{SOURCE_HEIGHT})");
+                continue;
+            }
+            Err(err) => {
+                warn!("Skipping {path:?}: {err}");
+                continue;
+            }
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        for (suffix, preprocessing) in [("before", None), ("after", Some(*settings))] {
+            ditherer.set_preprocessing(preprocessing);
+            let out_path = out_dir.join(format!("{stem}-{suffix}.png"));
+            if let Err(err) = ditherer.dither_image(&frame, None).save(&out_path) {
+                error!("Failed to write {out_path:?}: {err}");
+            }
+        }
+        info!("Compared {path:?}");
+    }
+    Ok(())
+}
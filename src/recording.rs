@@ -0,0 +1,162 @@
+//! Recording and replaying of all keys the game receives.
+//!
+//! Each line of a recording is `<tic> <millis> <key> <pressed>`. The game polls
+//! all pending keys once per tic, until it gets none. Tic is the number of such
+//! polls before the one that received the key, which is what gets used for
+//! replaying, so every key reaches the same tic of the game again. Millis are
+//! only informational.
+
+use doomgeneric::input::KeyData;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// Env var with a file path to record all keys to
+pub const RECORD_ENV: &str = "DOOMARKABLE_RECORD";
+/// Env var with a file path to a recording that replaces live input
+pub const REPLAY_ENV: &str = "DOOMARKABLE_REPLAY";
+
+const HEADER: &str = "# doomarkable input recording v2";
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{HEADER}")?;
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, tic: u64, keydata: &KeyData) {
+        let millis = self.start.elapsed().as_millis();
+        let result = writeln!(
+            self.writer,
+            "{} {} {} {}",
+            tic, millis, keydata.key, keydata.pressed as u8
+        )
+        // Flush right away to not lose anything when the game crashes or gets killed
+        .and_then(|_| self.writer.flush());
+        if let Err(err) = result {
+            error!("Failed to record key: {err}");
+        }
+    }
+}
+
+struct RecordedKey {
+    tic: u64,
+    key: u8,
+    pressed: bool,
+}
+
+pub struct Replayer {
+    keys: VecDeque<RecordedKey>,
+}
+
+impl Replayer {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        if content.lines().next() != Some(HEADER) {
+            return Err(format!("Not a recording of this version ({HEADER:?})"));
+        }
+        let mut keys = VecDeque::new();
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match fields[..] {
+                [tic, _millis, key, pressed] => {
+                    tic.parse().ok().zip(key.parse().ok()).zip(match pressed {
+                        "0" => Some(false),
+                        "1" => Some(true),
+                        _ => None,
+                    })
+                }
+                _ => None,
+            };
+            match parsed {
+                Some(((tic, key), pressed)) => keys.push_back(RecordedKey { tic, key, pressed }),
+                None => return Err(format!("Invalid line {}: {line:?}", line_no + 1)),
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    /// Next key that the game received in this tic of the recording
+    pub fn next_key(&mut self, tic: u64) -> Option<KeyData> {
+        if self.keys.front()?.tic > tic {
+            return None;
+        }
+        self.keys.pop_front().map(|recorded| KeyData {
+            key: recorded.key,
+            pressed: recorded.pressed,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("doomarkable-{}-{name}", std::process::id()))
+    }
+
+    /// Polls the way the game does: keys until there are none left, once per tic
+    fn replay_tics(replayer: &mut Replayer, tics: u64) -> Vec<Vec<(u8, bool)>> {
+        (0..tics)
+            .map(|tic| {
+                std::iter::from_fn(|| replayer.next_key(tic))
+                    .map(|keydata| (keydata.key, keydata.pressed))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replays_keys_in_their_tics() {
+        let path = temp_path("replay");
+        let mut recorder = Recorder::create(&path).unwrap();
+        let keys = [(0, 1, true), (0, 2, true), (1, 1, false), (3, 2, false)];
+        for (tic, key, pressed) in keys {
+            recorder.record(tic, &KeyData { key, pressed });
+        }
+        drop(recorder);
+
+        let mut replayer = Replayer::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            replay_tics(&mut replayer, 5),
+            vec![
+                vec![(1, true), (2, true)],
+                vec![(1, false)],
+                vec![],
+                vec![(2, false)],
+                vec![],
+            ]
+        );
+        assert!(replayer.is_finished());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let path = temp_path("version");
+        std::fs::write(&path, "# doomarkable input recording v1\n3 100 32 1\n").unwrap();
+        let result = Replayer::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
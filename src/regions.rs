@@ -0,0 +1,248 @@
+//! Finds the parts of a frame that are flat graphics instead of the 3D view: the status
+//! bar, the automap and menus. They get a plain threshold instead of blue noise, so thin
+//! lines and digits don't turn into speckle. Automap and menus are guessed from colors.
+
+use crate::doom_hud;
+use crate::render_scale::{SOURCE_HEIGHT, SOURCE_WIDTH};
+use libremarkable::image::{GrayImage, Luma, Rgb, RgbImage};
+
+/// The automap has a black background, the 3D view rarely is this black
+const AUTOMAP_BLACK_PERCENTAGE: usize = 60;
+/// Menus are written in saturated red. Fewer pixels are likely blood or the like.
+const MENU_MIN_PIXELS: usize = 400;
+/// Menus are centered. Red spread over the whole frame is a damage or berserk flash.
+const MENU_AREA_LEFT: u32 = SOURCE_WIDTH * 3 / 20;
+const MENU_AREA_RIGHT: u32 = SOURCE_WIDTH * 17 / 20;
+/// Share of the red pixels that may be outside of the menu area
+const MENU_OUTSIDE_PERCENTAGE: usize = 10;
+/// Around the red pixels (for shading and the skull cursor)
+const MENU_MARGIN: u32 = 8;
+/// Digits are bright red or yellow on dark boxes. The brightest channel
+/// separates them better from the gray stone than the average does.
+const THRESHOLD: u8 = 96;
+const OUTLINE_WIDTH: u32 = 2;
+
+#[inline]
+pub fn is_bright(pixel: &Rgb<u8>) -> bool {
+    pixel.0[0].max(pixel.0[1]).max(pixel.0[2]) > THRESHOLD
+}
+
+#[inline]
+fn is_menu_red(pixel: &Rgb<u8>) -> bool {
+    pixel.0[0] >= 160 && pixel.0[1] < 64 && pixel.0[2] < 64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    StatusBar,
+    Automap,
+    Menu,
+}
+
+impl RegionKind {
+    /// Length of the dashes of the debug outlines (in output pixels), to tell them apart
+    fn outline_dash(self) -> u32 {
+        match self {
+            RegionKind::StatusBar => 6,
+            RegionKind::Automap => 16,
+            RegionKind::Menu => 3,
+        }
+    }
+}
+
+/// Rect in frame (320x200) coordinates
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub kind: RegionKind,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How a single pixel gets dithered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Treatment {
+    BlueNoise,
+    Dark,
+    Bright,
+}
+
+/// Expects the downscaled frame, which might be cut off above the status bar
+pub fn detect(frame: &RgbImage) -> Vec<Region> {
+    let (width, height) = frame.dimensions();
+    let mut regions = vec![];
+    let view_rows = if height == SOURCE_HEIGHT {
+        regions.push(Region {
+            kind: RegionKind::StatusBar,
+            x: 0,
+            y: height - doom_hud::SOURCE_ROWS,
+            width,
+            height: doom_hud::SOURCE_ROWS,
+        });
+        height - doom_hud::SOURCE_ROWS
+    } else {
+        height
+    };
+
+    let mut black = 0;
+    let mut menu_pixels = 0;
+    let mut menu_pixels_outside = 0;
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, view_rows, 0, 0);
+    for y in 0..view_rows {
+        for x in 0..width {
+            let pixel = frame.get_pixel(x, y);
+            if pixel.0 == [0, 0, 0] {
+                black += 1;
+            } else if is_menu_red(pixel) {
+                menu_pixels += 1;
+                if !(MENU_AREA_LEFT..MENU_AREA_RIGHT).contains(&x) {
+                    menu_pixels_outside += 1;
+                }
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+
+    let view_pixels = width as usize * view_rows as usize;
+    if black * 100 >= view_pixels * AUTOMAP_BLACK_PERCENTAGE {
+        regions.push(Region {
+            kind: RegionKind::Automap,
+            x: 0,
+            y: 0,
+            width,
+            height: view_rows,
+        });
+    } else if menu_pixels >= MENU_MIN_PIXELS
+        && menu_pixels_outside * 100 <= menu_pixels * MENU_OUTSIDE_PERCENTAGE
+    {
+        let (x, y) = (
+            min_x.saturating_sub(MENU_MARGIN),
+            min_y.saturating_sub(MENU_MARGIN),
+        );
+        regions.push(Region {
+            kind: RegionKind::Menu,
+            x,
+            y,
+            width: (max_x + 1 + MENU_MARGIN).min(width) - x,
+            height: (max_y + 1 + MENU_MARGIN).min(view_rows) - y,
+        });
+    }
+    regions
+}
+
+/// Treatment of every pixel of the frame, row by row
+pub fn treatments(frame: &RgbImage, regions: &[Region]) -> Vec<Treatment> {
+    let (width, height) = frame.dimensions();
+    let mut treatments = vec![Treatment::BlueNoise; width as usize * height as usize];
+    for region in regions {
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                treatments[(y * width + x) as usize] = if is_bright(frame.get_pixel(x, y)) {
+                    Treatment::Bright
+                } else {
+                    Treatment::Dark
+                };
+            }
+        }
+    }
+    treatments
+}
+
+/// Dashed outlines around the regions, for checking the detection.
+/// The image can be any scale of the frame.
+pub fn draw_outlines(img: &mut GrayImage, regions: &[Region], frame_size: (u32, u32)) {
+    let (width, height) = img.dimensions();
+    let scale_x = |x: u32| x * width / frame_size.0;
+    let scale_y = |y: u32| y * height / frame_size.1;
+    for region in regions {
+        let dash_length = region.kind.outline_dash();
+        let mut dash = |x: u32, y: u32, along: u32| {
+            if x < width && y < height {
+                let bright = (along / dash_length) & 1 == 0;
+                img.put_pixel(x, y, Luma([if bright { 255 } else { 0 }]));
+            }
+        };
+        let (left, top) = (scale_x(region.x), scale_y(region.y));
+        let (right, bottom) = (
+            scale_x(region.x + region.width),
+            scale_y(region.y + region.height),
+        );
+        for offset in 0..OUTLINE_WIDTH {
+            for x in left..right {
+                dash(x, top + offset, x);
+                dash(x, bottom.saturating_sub(1 + offset), x);
+            }
+            for y in top..bottom {
+                dash(left + offset, y, y);
+                dash(right.saturating_sub(1 + offset), y, y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAY: Rgb<u8> = Rgb([90, 90, 90]);
+    const RED: Rgb<u8> = Rgb([200, 20, 20]);
+
+    fn kinds(frame: &RgbImage) -> Vec<RegionKind> {
+        detect(frame).iter().map(|region| region.kind).collect()
+    }
+
+    #[test]
+    fn finds_the_status_bar() {
+        let frame = RgbImage::from_pixel(SOURCE_WIDTH, SOURCE_HEIGHT, GRAY);
+        assert_eq!(kinds(&frame), vec![RegionKind::StatusBar]);
+
+        let cut_off = RgbImage::from_pixel(SOURCE_WIDTH, SOURCE_HEIGHT - 32, GRAY);
+        assert_eq!(kinds(&cut_off), vec![]);
+    }
+
+    #[test]
+    fn finds_the_automap() {
+        let frame = RgbImage::from_pixel(SOURCE_WIDTH, SOURCE_HEIGHT, Rgb([0, 0, 0]));
+        assert_eq!(
+            kinds(&frame),
+            vec![RegionKind::StatusBar, RegionKind::Automap]
+        );
+    }
+
+    #[test]
+    fn finds_a_centered_menu() {
+        let mut frame = RgbImage::from_pixel(SOURCE_WIDTH, SOURCE_HEIGHT, GRAY);
+        for y in 60..100 {
+            for x in 100..200 {
+                frame.put_pixel(x, y, RED);
+            }
+        }
+        let regions = detect(&frame);
+        let menu = regions
+            .iter()
+            .find(|region| region.kind == RegionKind::Menu)
+            .unwrap();
+        assert_eq!((menu.x, menu.y), (100 - MENU_MARGIN, 60 - MENU_MARGIN));
+        assert_eq!(
+            (menu.width, menu.height),
+            (100 + MENU_MARGIN * 2, 40 + MENU_MARGIN * 2)
+        );
+    }
+
+    #[test]
+    fn ignores_red_flashes() {
+        // Every fourth pixel turns red, like when taking damage
+        let frame = RgbImage::from_fn(SOURCE_WIDTH, SOURCE_HEIGHT, |x, y| {
+            if (x + y) % 4 == 0 {
+                RED
+            } else {
+                GRAY
+            }
+        });
+        assert_eq!(kinds(&frame), vec![RegionKind::StatusBar]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Size of the game on the display as a multiple of doom's native 320x200.
+//! Each scale has its own dither cache (see build/main.rs), since every source
+//! pixel gets dithered into a block of scale x scale display pixels.
+
+use doomgeneric::game;
+use libremarkable::cgmath::Point2;
+use libremarkable::framebuffer::common;
+
+/// Resolution the game gets dithered at (doomgeneric outputs it upscaled 2x)
+pub const SOURCE_WIDTH: u32 = game::DOOMGENERIC_RESX as u32 / 2;
+pub const SOURCE_HEIGHT: u32 = game::DOOMGENERIC_RESY as u32 / 2;
+
+/// Top of the game in portrait (below the title)
+const PORTRAIT_AREA_TOP: i32 = 62 + 140;
+/// Height of the area between title and layouts (fits the largest scale)
+const PORTRAIT_AREA_HEIGHT: i32 = SOURCE_HEIGHT as i32 * 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderScale {
+    /// 640x400. Smallest and fastest, meant for the rM 2.
+    X2,
+    /// 960x600
+    X3,
+    /// 1280x800
+    X4,
+}
+
+impl RenderScale {
+    pub fn factor(self) -> u32 {
+        match self {
+            RenderScale::X2 => 2,
+            RenderScale::X3 => 3,
+            RenderScale::X4 => 4,
+        }
+    }
+
+    pub fn from_factor(factor: u32) -> Option<Self> {
+        match factor {
+            2 => Some(RenderScale::X2),
+            3 => Some(RenderScale::X3),
+            4 => Some(RenderScale::X4),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> String {
+        format!("{}x", self.factor())
+    }
+
+    /// For cycling through the scales in the settings
+    pub fn next(self) -> Self {
+        match self {
+            RenderScale::X2 => RenderScale::X3,
+            RenderScale::X3 => RenderScale::X4,
+            RenderScale::X4 => RenderScale::X2,
+        }
+    }
+
+    /// Size of the dithered game in pixels
+    pub fn game_size(self) -> (u32, u32) {
+        (SOURCE_WIDTH * self.factor(), SOURCE_HEIGHT * self.factor())
+    }
+
+    /// Top left corner of the game in portrait. Smaller scales are centered
+    /// in the area the largest one would cover, so layouts stay in place.
+    pub fn portrait_pos(self) -> Point2<i32> {
+        let (width, height) = self.game_size();
+        Point2 {
+            x: (common::DISPLAYWIDTH as i32 - width as i32) / 2,
+            y: PORTRAIT_AREA_TOP + (PORTRAIT_AREA_HEIGHT - height as i32) / 2,
+        }
+    }
+
+    /// Zstd compressed cache, calculated in build/main.rs
+    pub fn compressed_dither_cache(self) -> &'static [u8] {
+        match self {
+            RenderScale::X2 => include_bytes!(env!("OUT_DIR_DITHERCACHE_2X_FILE")),
+            RenderScale::X3 => include_bytes!(env!("OUT_DIR_DITHERCACHE_3X_FILE")),
+            RenderScale::X4 => include_bytes!(env!("OUT_DIR_DITHERCACHE_4X_FILE")),
+        }
+    }
+}
+
+/// Doom was made for 320x200 shown on 4:3 screens, so its pixels are 1.2x taller than wide
+const PIXEL_ASPECT: f32 = 1.2;
+
+/// How the game gets sized in fullscreen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenScale {
+    /// Same image as in portrait, leaving room for the controls left and right
+    Blocks,
+    /// Largest whole multiple that fits the display
+    Integer,
+    /// As large as the display allows
+    Fit,
+}
+
+impl FullscreenScale {
+    pub fn name(self) -> &'static str {
+        match self {
+            FullscreenScale::Blocks => "blocks",
+            FullscreenScale::Integer => "integer",
+            FullscreenScale::Fit => "fit",
+        }
+    }
+
+    /// Whether the game gets too wide for the controls beside it. They still react
+    /// to touches, but aren't drawn.
+    pub fn covers_controls(self) -> bool {
+        self != FullscreenScale::Blocks
+    }
+
+    /// Size to resample the game to, or None to use the portrait image.
+    /// With aspect correction only the width of integer scaling stays a whole multiple.
+    pub fn target_size(self, display: (u32, u32), aspect_correction: bool) -> Option<(u32, u32)> {
+        let source_width = SOURCE_WIDTH as f32;
+        let source_height = if aspect_correction {
+            SOURCE_HEIGHT as f32 * PIXEL_ASPECT
+        } else {
+            SOURCE_HEIGHT as f32
+        };
+        let factor = (display.0 as f32 / source_width).min(display.1 as f32 / source_height);
+        let factor = match self {
+            FullscreenScale::Blocks => return None,
+            FullscreenScale::Integer => factor.floor().max(1.0),
+            FullscreenScale::Fit => factor,
+        };
+        Some((
+            ((source_width * factor).round() as u32).min(display.0),
+            ((source_height * factor).round() as u32).min(display.1),
+        ))
+    }
+}
@@ -0,0 +1,167 @@
+//! Mapping between rotated (logical) coordinates and the native portrait framebuffer
+
+use libremarkable::cgmath::{Point2, Vector2};
+use libremarkable::framebuffer::common;
+use libremarkable::framebuffer::core::Framebuffer;
+use libremarkable::framebuffer::{FramebufferDraw, FramebufferIO};
+
+/// Clockwise rotation of the content relative to the native portrait orientation.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Clockwise,
+    UpsideDown,
+    CounterClockwise,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation::None
+    }
+}
+
+impl Rotation {
+    pub fn is_landscape(self) -> bool {
+        matches!(self, Rotation::Clockwise | Rotation::CounterClockwise)
+    }
+
+    /// Size of the whole display when looked at in this rotation
+    pub fn logical_display_size(self) -> (u32, u32) {
+        let (width, height) = (common::DISPLAYWIDTH as u32, common::DISPLAYHEIGHT as u32);
+        if self.is_landscape() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Map a point inside a logical box of the given size to the rotated box
+    #[inline]
+    pub fn map_point(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Clockwise => (height - 1 - y, x),
+            Rotation::UpsideDown => (width - 1 - x, height - 1 - y),
+            Rotation::CounterClockwise => (y, width - 1 - x),
+        }
+    }
+
+    /// Inverse of [`Rotation::map_point`]. Width and height are still the logical size.
+    #[inline]
+    pub fn unmap_point(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Clockwise => (y, height - 1 - x),
+            Rotation::UpsideDown => (width - 1 - x, height - 1 - y),
+            Rotation::CounterClockwise => (width - 1 - y, x),
+        }
+    }
+
+    /// Convert a rect in logical display coordinates to native framebuffer coordinates
+    pub fn to_screen_rect(self, rect: &common::mxcfb_rect) -> common::mxcfb_rect {
+        let (logical_width, logical_height) = self.logical_display_size();
+        match self {
+            Rotation::None => *rect,
+            Rotation::Clockwise => common::mxcfb_rect {
+                left: logical_height - (rect.top + rect.height),
+                top: rect.left,
+                width: rect.height,
+                height: rect.width,
+            },
+            Rotation::UpsideDown => common::mxcfb_rect {
+                left: logical_width - (rect.left + rect.width),
+                top: logical_height - (rect.top + rect.height),
+                width: rect.width,
+                height: rect.height,
+            },
+            Rotation::CounterClockwise => common::mxcfb_rect {
+                left: rect.top,
+                top: logical_width - (rect.left + rect.width),
+                width: rect.height,
+                height: rect.width,
+            },
+        }
+    }
+
+    /// Convert a point on the native framebuffer (e.g. a touch) to logical display coordinates
+    pub fn from_screen_point(self, x: u32, y: u32) -> (u32, u32) {
+        let (logical_width, logical_height) = self.logical_display_size();
+        let x = x.min(common::DISPLAYWIDTH as u32 - 1);
+        let y = y.min(common::DISPLAYHEIGHT as u32 - 1);
+        self.unmap_point(x, y, logical_width, logical_height)
+    }
+}
+
+/// Same as `Framebuffer::draw_text` but with pos and the returned rect being logical coordinates.
+///
+/// libremarkable can only draw upright text. So the text is drawn upright into a scratch
+/// area (which gets restored afterwards) and then copied rotated into its destination.
+pub fn draw_text(
+    fb: &mut Framebuffer,
+    rotation: Rotation,
+    pos: Point2<f32>,
+    text: &str,
+    size: f32,
+    col: common::color,
+    dryrun: bool,
+) -> common::mxcfb_rect {
+    if rotation == Rotation::None || dryrun || text.is_empty() {
+        return fb.draw_text(pos, text, size, col, dryrun);
+    }
+
+    const PAD: u32 = 10;
+    let measured = fb.draw_text(Point2 { x: 0f32, y: 500f32 }, text, size, col, true);
+    let scratch = common::mxcfb_rect {
+        left: 0,
+        top: 0,
+        width: measured.width + PAD * 2,
+        height: measured.height * 2 + PAD * 2,
+    };
+    let backup = fb.dump_region(scratch).unwrap();
+    fb.fill_rect(
+        Point2 { x: 0, y: 0 },
+        Vector2 {
+            x: scratch.width,
+            y: scratch.height,
+        },
+        common::color::WHITE,
+    );
+    let baseline = Point2 {
+        x: PAD as f32,
+        y: (PAD + measured.height) as f32,
+    };
+    let drawn = fb.draw_text(baseline, text, size, col, false);
+    let drawn = common::mxcfb_rect {
+        left: drawn.left,
+        top: drawn.top,
+        width: drawn.width.min(scratch.width - drawn.left),
+        height: drawn.height.min(scratch.height - drawn.top),
+    };
+    let text_pixels = fb.dump_region(drawn).unwrap();
+    fb.restore_region(scratch, &backup).unwrap();
+
+    // Where the text would have ended up when drawn upright at pos
+    let logical_rect = common::mxcfb_rect {
+        left: (pos.x + drawn.left as f32 - baseline.x) as u32,
+        top: (pos.y + drawn.top as f32 - baseline.y) as u32,
+        width: drawn.width,
+        height: drawn.height,
+    };
+    let screen_rect = rotation.to_screen_rect(&logical_rect);
+    let mut screen_pixels = fb.dump_region(screen_rect).unwrap();
+    for y in 0..drawn.height {
+        for x in 0..drawn.width {
+            let src = ((y * drawn.width + x) * 2) as usize;
+            if text_pixels[src] == 0xFF && text_pixels[src + 1] == 0xFF {
+                continue; // Only copy the text itself and keep the background
+            }
+            let (new_x, new_y) = rotation.map_point(x, y, drawn.width, drawn.height);
+            let dst = ((new_y * screen_rect.width + new_x) * 2) as usize;
+            screen_pixels[dst] = text_pixels[src];
+            screen_pixels[dst + 1] = text_pixels[src + 1];
+        }
+    }
+    fb.restore_region(screen_rect, &screen_pixels).unwrap();
+
+    logical_rect
+}
@@ -0,0 +1,236 @@
+//! Savegame files of the game. Each IWAD gets its own directory, so the slots of
+//! e.g. doom.wad and doom2.wad don't overwrite each other. The game runs with that
+//! directory as its working directory, since it puts the saves there.
+
+use crate::clock::LocalTime;
+use crate::macros::Macro;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+/// Number of slots in the save and load menus of the game
+pub const SLOTS: usize = 6;
+
+/// Where the IWAD files are expected (and where saves were kept before)
+const WAD_DIR: &str = "/home/root";
+const SAVES_DIR: &str = "/home/root/.local/share/doomarkable/saves";
+const BACKUPS_DIR_NAME: &str = "backups";
+
+/// Layout of the savegame header (see p_saveg.c)
+const DESCRIPTION_LEN: usize = 24;
+const VERSION_LEN: usize = 16;
+
+/// In the order the game looks for them (see d_iwad.c)
+const IWADS: &[&str] = &[
+    "doom2.wad",
+    "plutonia.wad",
+    "tnt.wad",
+    "doom.wad",
+    "doom1.wad",
+    "chex.wad",
+    "hacx.wad",
+    "freedm.wad",
+    "freedoom2.wad",
+    "freedoom1.wad",
+];
+
+/// IWADs with maps named MAPxx instead of ExMy
+const COMMERCIAL_IWADS: &[&str] = &[
+    "doom2.wad",
+    "plutonia.wad",
+    "tnt.wad",
+    "hacx.wad",
+    "freedm.wad",
+    "freedoom2.wad",
+];
+
+/// File name of the IWAD the game will use (lowercase)
+static IWAD: LazyLock<Option<String>> = LazyLock::new(find_iwad);
+
+fn find_iwad() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "-iwad") {
+        if let Some(path) = args.get(index + 1) {
+            let name = Path::new(path).file_name()?.to_string_lossy();
+            return Some(name.to_ascii_lowercase());
+        }
+    }
+    IWADS
+        .iter()
+        .find(|iwad| Path::new(WAD_DIR).join(iwad).exists())
+        .map(|iwad| iwad.to_string())
+}
+
+pub fn iwad_name() -> Option<&'static str> {
+    IWAD.as_deref()
+}
+
+pub fn saves_dir() -> PathBuf {
+    let name = match iwad_name() {
+        Some(iwad) => iwad.trim_end_matches(".wad"),
+        None => "unknown",
+    };
+    Path::new(SAVES_DIR).join(name)
+}
+
+fn backups_dir() -> PathBuf {
+    saves_dir().join(BACKUPS_DIR_NAME)
+}
+
+fn slot_file_name(slot: usize) -> String {
+    format!("doomsav{slot}.dsg")
+}
+
+pub fn slot_path(slot: usize) -> PathBuf {
+    saves_dir().join(slot_file_name(slot))
+}
+
+/// Make the saves directory of the IWAD the working directory. Saves from before they
+/// got separated are moved into it. The game then finds the IWAD with DOOMWADDIR.
+pub fn enter_saves_dir() -> std::io::Result<()> {
+    let dir = saves_dir();
+    std::fs::create_dir_all(&dir)?;
+
+    for slot in 0..SLOTS {
+        let old_path = Path::new(WAD_DIR).join(slot_file_name(slot));
+        let new_path = dir.join(slot_file_name(slot));
+        if old_path.exists() && !new_path.exists() {
+            info!("Moving {old_path:?} to {new_path:?}");
+            std::fs::rename(&old_path, &new_path)?;
+        }
+    }
+
+    std::env::set_current_dir(&dir)?;
+    std::env::set_var("DOOMWADDIR", WAD_DIR);
+    info!("Using saves in {dir:?} for {:?}", iwad_name());
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct SaveInfo {
+    /// Name given when saving
+    pub description: String,
+    /// E.g. "E1M3" or "MAP07"
+    pub map: String,
+    pub modified: Option<SystemTime>,
+}
+
+pub fn read_slot(slot: usize) -> Option<SaveInfo> {
+    let path = slot_path(slot);
+    let data = std::fs::read(&path).ok()?;
+    let header_len = DESCRIPTION_LEN + VERSION_LEN + 3;
+    if data.len() < header_len {
+        warn!("Savegame {path:?} is too short");
+        return None;
+    }
+
+    let description = &data[..DESCRIPTION_LEN];
+    let description_end = description
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(DESCRIPTION_LEN);
+    let description = String::from_utf8_lossy(&description[..description_end]).into_owned();
+    let (episode, map) = (
+        data[DESCRIPTION_LEN + VERSION_LEN + 1],
+        data[DESCRIPTION_LEN + VERSION_LEN + 2],
+    );
+    let map = if COMMERCIAL_IWADS.contains(&iwad_name().unwrap_or_default()) {
+        format!("MAP{map:02}")
+    } else {
+        format!("E{episode}M{map}")
+    };
+
+    Some(SaveInfo {
+        description,
+        map,
+        modified: std::fs::metadata(&path).and_then(|m| m.modified()).ok(),
+    })
+}
+
+/// Menu items of the load menu can be selected by their number
+pub fn load_macro(slot: usize) -> Macro {
+    Macro::parse(&format!(
+        "Load slot {}: <f3> <wait:300> {} <enter>",
+        slot + 1,
+        slot + 1
+    ))
+    .unwrap()
+}
+
+/// Copy into the first free slot
+pub fn duplicate(slot: usize) {
+    let free_slot = match (0..SLOTS).find(|slot| !slot_path(*slot).exists()) {
+        Some(free_slot) => free_slot,
+        None => {
+            warn!("No free slot to duplicate slot {} into", slot + 1);
+            return;
+        }
+    };
+    if let Err(err) = std::fs::copy(slot_path(slot), slot_path(free_slot)) {
+        error!("Failed to duplicate slot {}: {err}", slot + 1);
+    }
+}
+
+/// The save gets moved into a backup instead of being lost
+pub fn delete(slot: usize) {
+    let dir = backups_dir().join(format!("{} deleted", LocalTime::now().file_name()));
+    let result = std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::rename(slot_path(slot), dir.join(slot_file_name(slot))));
+    if let Err(err) = result {
+        error!("Failed to delete slot {}: {err}", slot + 1);
+    }
+}
+
+fn backup_into(name: &str) -> std::io::Result<()> {
+    let dir = backups_dir().join(name);
+    std::fs::create_dir_all(&dir)?;
+    for slot in 0..SLOTS {
+        if slot_path(slot).exists() {
+            std::fs::copy(slot_path(slot), dir.join(slot_file_name(slot)))?;
+        }
+    }
+    info!("Backed up saves into {dir:?}");
+    Ok(())
+}
+
+/// Copy all slots into a new timestamped backup
+pub fn backup() {
+    if let Err(err) = backup_into(&LocalTime::now().file_name()) {
+        error!("Failed to back up saves: {err}");
+    }
+}
+
+/// Name (timestamp) of the newest backup made with [`backup`]
+pub fn latest_backup() -> Option<String> {
+    std::fs::read_dir(backups_dir())
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.contains(' ')) // Skip deleted saves and restore backups
+        .max()
+}
+
+/// Replace all slots with the newest backup. The current slots get backed up before.
+pub fn restore_latest_backup() {
+    let name = match latest_backup() {
+        Some(name) => name,
+        None => return,
+    };
+    let result =
+        backup_into(&format!("{} before restore", LocalTime::now().file_name())).and_then(|_| {
+            let dir = backups_dir().join(&name);
+            for slot in 0..SLOTS {
+                let backup_path = dir.join(slot_file_name(slot));
+                if backup_path.exists() {
+                    std::fs::copy(&backup_path, slot_path(slot))?;
+                } else if slot_path(slot).exists() {
+                    std::fs::remove_file(slot_path(slot))?;
+                }
+            }
+            Ok(())
+        });
+    match result {
+        Ok(()) => info!("Restored saves from backup {name:?}"),
+        Err(err) => error!("Failed to restore backup {name:?}: {err}"),
+    }
+}
@@ -0,0 +1,104 @@
+//! Orderly exit. The input thread (or a SIGTERM/SIGINT) requests it, the render thread
+//! stops drawing and the game thread finishes the process between two frames, so the
+//! engine never gets killed in the middle of one.
+
+use crate::config::CONFIG;
+use crate::evdev_keyboard;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Max time to wait for the render thread to finish its current frame
+const RENDER_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static RENDER_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// What is left on the screen after exiting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExitScreen {
+    #[default]
+    Clear,
+    Goodbye,
+}
+
+impl ExitScreen {
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitScreen::Clear => "clear",
+            ExitScreen::Goodbye => "goodbye",
+        }
+    }
+}
+
+extern "C" fn on_signal(_signal: libc::c_int) {
+    // Only atomics are safe to use in here
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+pub fn install_signal_handlers() {
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            error!(
+                "Failed to install handler for signal {signal}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Called by the render thread once it won't touch the framebuffer anymore
+pub fn render_stopped() {
+    RENDER_STOPPED.store(true, Ordering::Relaxed);
+}
+
+/// Release everything, leave the configured screen and run the post exit command.
+/// Called by the game thread between frames.
+pub fn finish() -> ! {
+    info!("Shutting down...");
+    let start = Instant::now();
+    while !RENDER_STOPPED.load(Ordering::Relaxed) && start.elapsed() < RENDER_STOP_TIMEOUT {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if !RENDER_STOPPED.load(Ordering::Relaxed) {
+        warn!("Render thread didn't stop in time");
+    }
+
+    evdev_keyboard::release_grabs();
+    let config = CONFIG.lock().unwrap().clone();
+    config.save();
+
+    match config.exit_screen {
+        ExitScreen::Clear => {
+            crate::clear();
+            crate::full_refresh();
+        }
+        ExitScreen::Goodbye => crate::draw_message_screen("Goodbye!", None),
+    }
+
+    if let Some(command) = &config.post_exit_command {
+        info!("Running post exit command {command:?}");
+        match std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .status()
+        {
+            Ok(status) if !status.success() => {
+                warn!("Post exit command failed ({status})")
+            }
+            Ok(_) => {}
+            Err(err) => error!("Failed to run post exit command: {err}"),
+        }
+    }
+
+    info!("Bye");
+    std::process::exit(0);
+}
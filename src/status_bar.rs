@@ -0,0 +1,386 @@
+//! Line at the top of the screen with the battery, clock, play time and frame rate.
+//! Each part only gets redrawn and refreshed when its text changes.
+
+use crate::battery::{BatterySource, BatteryStatus, ChargeState};
+use crate::clock::LocalTime;
+use crate::rotation::{self, Rotation};
+use libremarkable::cgmath::{Point2, Vector2};
+use libremarkable::framebuffer::core::Framebuffer;
+use libremarkable::framebuffer::{common, PartialRefreshMode};
+use libremarkable::framebuffer::{FramebufferDraw, FramebufferRefresh};
+use std::time::{Duration, Instant};
+
+const BATTERY_READ_INTERVAL: Duration = Duration::from_secs(30);
+const FPS_INTERVAL: Duration = Duration::from_secs(5);
+const TOAST_DURATION: Duration = Duration::from_secs(15);
+/// Below this the battery is shown as low (unless charging)
+const LOW_BATTERY_PERCENTAGE: u8 = 15;
+
+const TEXT_SIZE: f32 = 30.0;
+const TOP: u32 = 10;
+const HEIGHT: u32 = 36;
+/// Battery icon, drawn in front of the battery text
+const ICON_WIDTH: u32 = 40;
+const ICON_HEIGHT: u32 = 20;
+const ICON_BORDER: u32 = 2;
+const ICON_NUB_WIDTH: u32 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Battery,
+    Clock,
+    Session,
+    Fps,
+    /// Short message, e.g. a warning
+    Toast,
+}
+
+impl Part {
+    const ALL: [Part; 5] = [
+        Part::Battery,
+        Part::Clock,
+        Part::Session,
+        Part::Fps,
+        Part::Toast,
+    ];
+
+    /// Area in logical (portrait) coordinates. Each text has to fit into its own.
+    fn rect(self) -> common::mxcfb_rect {
+        let (left, width) = match self {
+            Part::Battery => (10, 300),
+            Part::Clock => (330, 120),
+            Part::Session => (470, 250),
+            Part::Fps => (740, 170),
+            Part::Toast => (930, 1404 - 10 - 930),
+        };
+        common::mxcfb_rect {
+            left,
+            top: TOP,
+            width,
+            height: HEIGHT,
+        }
+    }
+}
+
+pub struct StatusBar {
+    battery_source: Box<dyn BatterySource + Send>,
+    battery: Option<BatteryStatus>,
+    last_battery_read: Option<Instant>,
+    session_start: Instant,
+    /// Time while suspended doesn't count as playing
+    suspended_total: Duration,
+    frames: u32,
+    fps_since: Instant,
+    fps: Option<f32>,
+    toast: Option<(String, Instant)>,
+    /// Text currently on screen per part (None if it needs to be drawn)
+    shown: [Option<String>; 5],
+}
+
+impl StatusBar {
+    pub fn new(battery_source: Box<dyn BatterySource + Send>) -> Self {
+        Self {
+            battery_source,
+            battery: None,
+            last_battery_read: None,
+            session_start: Instant::now(),
+            suspended_total: Duration::ZERO,
+            frames: 0,
+            fps_since: Instant::now(),
+            fps: None,
+            toast: None,
+            shown: Default::default(),
+        }
+    }
+
+    pub fn battery(&self) -> Option<BatteryStatus> {
+        self.battery
+    }
+
+    /// Read the battery if it's due. Returns true if it was read.
+    pub fn poll_battery(&mut self) -> bool {
+        if matches!(self.last_battery_read, Some(last) if last.elapsed() < BATTERY_READ_INTERVAL) {
+            return false;
+        }
+        self.last_battery_read = Some(Instant::now());
+        self.battery = self.battery_source.read();
+        if self.battery.is_none() {
+            debug!("Failed to read the battery state");
+        }
+        true
+    }
+
+    pub fn frame_drawn(&mut self) {
+        self.frames += 1;
+        let elapsed = self.fps_since.elapsed();
+        if elapsed >= FPS_INTERVAL {
+            self.fps = Some(self.frames as f32 / elapsed.as_secs_f32());
+            self.frames = 0;
+            self.fps_since = Instant::now();
+        }
+    }
+
+    pub fn add_suspended(&mut self, duration: Duration) {
+        self.suspended_total += duration;
+        // Frames weren't drawn while suspended
+        self.frames = 0;
+        self.fps_since = Instant::now();
+    }
+
+    /// Show a message for a while
+    pub fn show_toast(&mut self, text: &str) {
+        self.toast = Some((text.to_owned(), Instant::now()));
+    }
+
+    /// The bar is hidden in fullscreen, so the toast gets drawn into the top left corner
+    /// of the game instead. Needs to be called after every frame, before its refresh.
+    pub fn draw_toast_over_game(
+        &self,
+        fb: &mut Framebuffer,
+        rotation: Rotation,
+        game_pos: Point2<i32>,
+    ) {
+        let text = self.text(Part::Toast);
+        if text.is_empty() {
+            return;
+        }
+        let rect = common::mxcfb_rect {
+            left: game_pos.x.max(0) as u32 + TOP,
+            top: game_pos.y.max(0) as u32 + TOP,
+            width: Part::Toast.rect().width,
+            height: HEIGHT,
+        };
+        fill_rect(fb, rotation, &rect, common::color::WHITE);
+        rotation::draw_text(
+            fb,
+            rotation,
+            Point2 {
+                x: (rect.left + 10) as f32,
+                y: (rect.top + HEIGHT - 8) as f32,
+            },
+            &text,
+            TEXT_SIZE,
+            common::color::BLACK,
+            false,
+        );
+    }
+
+    /// Everything has to be drawn again (e.g. after the screen got cleared)
+    pub fn invalidate(&mut self) {
+        self.shown = Default::default();
+    }
+
+    fn text(&self, part: Part) -> String {
+        match part {
+            Part::Battery => match self.battery {
+                None => "?".to_owned(),
+                Some(BatteryStatus {
+                    state: ChargeState::Full,
+                    ..
+                }) => "Full".to_owned(),
+                Some(BatteryStatus {
+                    percentage,
+                    state: ChargeState::Charging,
+                }) => format!("{percentage}% charging"),
+                Some(BatteryStatus { percentage, .. }) if percentage <= LOW_BATTERY_PERCENTAGE => {
+                    format!("{percentage}% low")
+                }
+                Some(BatteryStatus { percentage, .. }) => format!("{percentage}%"),
+            },
+            Part::Clock => {
+                let now = LocalTime::now();
+                format!("{:02}:{:02}", now.hour, now.minute)
+            }
+            Part::Session => {
+                let minutes = (self
+                    .session_start
+                    .elapsed()
+                    .saturating_sub(self.suspended_total))
+                .as_secs()
+                    / 60;
+                format!("Played {}:{:02}", minutes / 60, minutes % 60)
+            }
+            Part::Fps => match self.fps {
+                Some(fps) => format!("{fps:.1} FPS"),
+                None => String::new(),
+            },
+            Part::Toast => match &self.toast {
+                Some((text, shown_since)) if shown_since.elapsed() < TOAST_DURATION => text.clone(),
+                _ => String::new(),
+            },
+        }
+    }
+
+    /// Redraw and refresh the parts whose text changed
+    pub fn draw(&mut self, fb: &mut Framebuffer, rotation: Rotation) {
+        for (index, part) in Part::ALL.iter().enumerate() {
+            let text = self.text(*part);
+            if self.shown[index].as_ref() == Some(&text) {
+                continue;
+            }
+
+            let rect = part.rect();
+            fill_rect(fb, rotation, &rect, common::color::WHITE);
+            let mut text_left = rect.left;
+            if *part == Part::Battery {
+                self.draw_battery_icon(fb, rotation, &rect);
+                text_left += ICON_WIDTH + ICON_NUB_WIDTH + 10;
+            }
+            rotation::draw_text(
+                fb,
+                rotation,
+                Point2 {
+                    x: text_left as f32,
+                    y: (rect.top + HEIGHT - 8) as f32,
+                },
+                &text,
+                TEXT_SIZE,
+                common::color::BLACK,
+                false,
+            );
+            fb.partial_refresh(
+                &rotation.to_screen_rect(&rect),
+                PartialRefreshMode::Async,
+                common::waveform_mode::WAVEFORM_MODE_GC16_FAST,
+                common::display_temp::TEMP_USE_MAX,
+                common::dither_mode::EPDC_FLAG_USE_REMARKABLE_DITHER,
+                0,
+                false,
+            );
+            self.shown[index] = Some(text);
+        }
+    }
+
+    /// Outline filled according to the charge. Charging adds a bolt, low battery a "!"
+    /// and a full battery is always filled completely.
+    fn draw_battery_icon(
+        &self,
+        fb: &mut Framebuffer,
+        rotation: Rotation,
+        rect: &common::mxcfb_rect,
+    ) {
+        let body = common::mxcfb_rect {
+            left: rect.left,
+            top: rect.top + (HEIGHT - ICON_HEIGHT) / 2,
+            width: ICON_WIDTH,
+            height: ICON_HEIGHT,
+        };
+        let nub = common::mxcfb_rect {
+            left: body.left + ICON_WIDTH,
+            top: body.top + ICON_HEIGHT / 4,
+            width: ICON_NUB_WIDTH,
+            height: ICON_HEIGHT / 2,
+        };
+        fill_rect(fb, rotation, &body, common::color::BLACK);
+        fill_rect(fb, rotation, &nub, common::color::BLACK);
+
+        let inner = common::mxcfb_rect {
+            left: body.left + ICON_BORDER,
+            top: body.top + ICON_BORDER,
+            width: ICON_WIDTH - ICON_BORDER * 2,
+            height: ICON_HEIGHT - ICON_BORDER * 2,
+        };
+        fill_rect(fb, rotation, &inner, common::color::WHITE);
+        // Area the charge level fills
+        let area = common::mxcfb_rect {
+            left: inner.left + ICON_BORDER,
+            top: inner.top + ICON_BORDER,
+            width: inner.width - ICON_BORDER * 2,
+            height: inner.height - ICON_BORDER * 2,
+        };
+        // Parts of the symbols, in the coordinates of the area
+        let part = |left: u32, top: u32, width: u32, height: u32| common::mxcfb_rect {
+            left: area.left + left,
+            top: area.top + top,
+            width,
+            height,
+        };
+
+        let battery = match self.battery {
+            Some(battery) => battery,
+            None => return, // The text shows "?"
+        };
+        let charge = match battery.state {
+            ChargeState::Full => 100,
+            _ => (battery.percentage as u32).min(100),
+        };
+        let level = part(0, 0, area.width * charge / 100, area.height);
+        if level.width > 0 {
+            fill_rect(fb, rotation, &level, common::color::BLACK);
+        }
+
+        match battery.state {
+            ChargeState::Charging => {
+                // Two offset strokes, outlined in white to stand out from the level
+                let strokes = [
+                    part(area.width / 2, 0, 6, area.height / 2),
+                    part(area.width / 2 - 6, area.height / 2, 6, area.height / 2),
+                ];
+                for stroke in &strokes {
+                    fill_rect(
+                        fb,
+                        rotation,
+                        &grown_within(stroke, &area),
+                        common::color::WHITE,
+                    );
+                }
+                for stroke in &strokes {
+                    fill_rect(fb, rotation, stroke, common::color::BLACK);
+                }
+            }
+            ChargeState::Full => {}
+            _ if battery.percentage <= LOW_BATTERY_PERCENTAGE => {
+                // At the right end, so the little level left stays visible
+                let right = area.width - 3;
+                fill_rect(
+                    fb,
+                    rotation,
+                    &part(right, 0, 3, area.height - 5),
+                    common::color::BLACK,
+                );
+                fill_rect(
+                    fb,
+                    rotation,
+                    &part(right, area.height - 3, 3, 3),
+                    common::color::BLACK,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Larger by one pixel on all sides, but not beyond the bounds
+fn grown_within(rect: &common::mxcfb_rect, bounds: &common::mxcfb_rect) -> common::mxcfb_rect {
+    let left = (rect.left - 1).max(bounds.left);
+    let top = (rect.top - 1).max(bounds.top);
+    let right = (rect.left + rect.width + 1).min(bounds.left + bounds.width);
+    let bottom = (rect.top + rect.height + 1).min(bounds.top + bounds.height);
+    common::mxcfb_rect {
+        left,
+        top,
+        width: right - left,
+        height: bottom - top,
+    }
+}
+
+/// Fill a rect given in logical coordinates
+fn fill_rect(
+    fb: &mut Framebuffer,
+    rotation: Rotation,
+    rect: &common::mxcfb_rect,
+    color: common::color,
+) {
+    let rect = rotation.to_screen_rect(rect);
+    fb.fill_rect(
+        Point2 {
+            x: rect.left as i32,
+            y: rect.top as i32,
+        },
+        Vector2 {
+            x: rect.width,
+            y: rect.height,
+        },
+        color,
+    );
+}
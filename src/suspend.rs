@@ -0,0 +1,70 @@
+//! Handling of the device going to sleep (power button) and of launchers stopping
+//! and continuing the process (SIGTSTP/SIGSTOP and SIGCONT).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const POWER_STATE_PATH: &str = "/sys/power/state";
+
+/// Set while a message screen ("Paused", "Saving...") is shown. Nothing else should be
+/// drawn meanwhile.
+pub static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static CONTINUED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// SIGTSTP: Prepare and stop the process
+    Stop,
+    /// SIGCONT: The process was stopped (maybe without asking) and runs again
+    Continued,
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    // Only atomics are safe to use in here
+    match signal {
+        libc::SIGTSTP => STOP_REQUESTED.store(true, Ordering::Relaxed),
+        libc::SIGCONT => CONTINUED.store(true, Ordering::Relaxed),
+        _ => {}
+    }
+}
+
+pub fn install_signal_handlers() {
+    for signal in [libc::SIGTSTP, libc::SIGCONT] {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            error!(
+                "Failed to install handler for signal {signal}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+pub fn take_request() -> Option<Request> {
+    if STOP_REQUESTED.swap(false, Ordering::Relaxed) {
+        Some(Request::Stop)
+    } else if CONTINUED.swap(false, Ordering::Relaxed) {
+        Some(Request::Continued)
+    } else {
+        None
+    }
+}
+
+pub fn is_suspended() -> bool {
+    SUSPENDED.load(Ordering::Relaxed)
+}
+
+/// Stop the whole process. Returns once it got continued.
+pub fn stop_process() {
+    unsafe {
+        libc::raise(libc::SIGSTOP);
+    }
+    // Resuming is handled right after this, so the SIGCONT is already taken care of
+    CONTINUED.store(false, Ordering::Relaxed);
+}
+
+/// Suspend the device to memory. Returns after it woke up again.
+pub fn sleep_device() -> std::io::Result<()> {
+    std::fs::write(POWER_STATE_PATH, "mem")
+}
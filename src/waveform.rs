@@ -0,0 +1,116 @@
+//! Picks the waveform for each refresh of the game. The fast waveforms leave ghosting
+//! behind, so the game rect gets a clean (GC16) refresh every now and then and right
+//! after scene changes (level transitions, menus, ...).
+
+use libremarkable::framebuffer::common;
+use libremarkable::image::GrayImage;
+use std::time::{Duration, Instant};
+
+/// Only every n-th pixel in both directions is compared to detect scene changes.
+/// Odd, so it doesn't line up with the 4x4 blocks of the dithering.
+const SAMPLE_STRIDE: usize = 3;
+
+/// When to clean up the ghosting (0 disables each)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanupSettings {
+    pub every_frames: u32,
+    pub every_seconds: u32,
+    /// Percentage of changed pixels that counts as a scene change
+    pub scene_change_percentage: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Refresh {
+    pub waveform: common::waveform_mode,
+    /// Flash the area, which removes the ghosting
+    pub full: bool,
+}
+
+pub struct WaveformPolicy {
+    settings: CleanupSettings,
+    frames_since_cleanup: u32,
+    last_cleanup: Instant,
+    /// Sampled pixels of the previous frame
+    previous_samples: Vec<u8>,
+}
+
+impl WaveformPolicy {
+    pub fn new(settings: CleanupSettings) -> Self {
+        Self {
+            settings,
+            frames_since_cleanup: 0,
+            last_cleanup: Instant::now(),
+            previous_samples: vec![],
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: CleanupSettings) {
+        self.settings = settings;
+    }
+
+    fn fast_waveform() -> common::waveform_mode {
+        match libremarkable::device::CURRENT_DEVICE.model {
+            libremarkable::device::Model::Gen1 => common::waveform_mode::WAVEFORM_MODE_GLR16,
+            libremarkable::device::Model::Gen2 => common::waveform_mode::WAVEFORM_MODE_DU,
+        }
+    }
+
+    /// Fraction of the sampled pixels that differ from the previous frame
+    fn changed_fraction(&mut self, frame: &GrayImage) -> f32 {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let raw = frame.as_raw();
+        let samples: Vec<u8> = (0..height)
+            .step_by(SAMPLE_STRIDE)
+            .flat_map(|y| {
+                (0..width)
+                    .step_by(SAMPLE_STRIDE)
+                    .map(move |x| y * width + x)
+            })
+            .map(|index| raw[index])
+            .collect();
+
+        let fraction = if samples.len() == self.previous_samples.len() && !samples.is_empty() {
+            let changed = samples
+                .iter()
+                .zip(&self.previous_samples)
+                .filter(|(a, b)| a != b)
+                .count();
+            changed as f32 / samples.len() as f32
+        } else {
+            0.0
+        };
+        self.previous_samples = samples;
+        fraction
+    }
+
+    pub fn choose(&mut self, frame: &GrayImage) -> Refresh {
+        self.frames_since_cleanup += 1;
+        let settings = self.settings;
+        let changed_fraction = self.changed_fraction(frame);
+
+        let scene_changed = settings.scene_change_percentage > 0
+            && changed_fraction * 100.0 >= settings.scene_change_percentage as f32;
+        let frames_due =
+            settings.every_frames > 0 && self.frames_since_cleanup >= settings.every_frames;
+        let time_due = settings.every_seconds > 0
+            && self.last_cleanup.elapsed() >= Duration::from_secs(settings.every_seconds as u64);
+
+        if scene_changed || frames_due || time_due {
+            debug!(
+                "Cleaning up ghosting (scene change: {scene_changed}, {:.0}% changed)",
+                changed_fraction * 100.0
+            );
+            self.frames_since_cleanup = 0;
+            self.last_cleanup = Instant::now();
+            return Refresh {
+                waveform: common::waveform_mode::WAVEFORM_MODE_GC16,
+                full: true,
+            };
+        }
+
+        Refresh {
+            waveform: Self::fast_waveform(),
+            full: false,
+        }
+    }
+}